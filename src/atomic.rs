@@ -0,0 +1,82 @@
+//! Saving without risking the previous version: the content is written to a temporary
+//! file next to the target, synced to the disk, then renamed over the target. A crash or
+//! a full disk while writing leaves the target as it was.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Error, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Counts the bytes written through it, to tell how far a failed write went
+struct Counter<W: Write>{
+    inner: W,
+    written: usize,
+}
+
+impl<W: Write> Write for Counter<W>{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error>{
+        let n = self.inner.write(buf)?;
+        self.written += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Error>{
+        self.inner.flush()
+    }
+}
+
+/// `data.csv.bak`, where the previous version of `data.csv` is kept
+#[must_use]
+pub fn backup_path(filename: &str) -> String{
+    format!("{filename}.bak")
+}
+
+/// Replaces `filename` by what `content` writes, keeping the permissions of the file replaced
+/// and, with `backup`, a copy of it in `<file>.bak`
+///
+/// # Errors
+/// Will return an error, saying what was written and that the file was left untouched, when
+/// the temporary file can't be written, synced or renamed over `filename`
+pub fn write<F>(filename: &str, backup: bool, content: F) -> Result<(), Error> where F: FnOnce(&mut dyn Write) -> Result<(), Error>{
+    // The target of a link is replaced, not the link
+    let target = fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+    let temp = temp_path(&target);
+    let failed = |e: Error, what: String| {
+        let _ = fs::remove_file(&temp);
+        Error::new(e.kind(), format!("{what}: {e}, {filename} was left untouched"))
+    };
+
+    let _ = fs::remove_file(&temp);
+    let file = OpenOptions::new().write(true).create_new(true).open(&temp)
+        .map_err(|e| failed(e, format!("Can't create {}", temp.display())))?;
+    let mut counter = Counter{inner: BufWriter::new(file), written: 0};
+    if let Err(e) = content(&mut counter).and_then(|()| counter.flush()){
+        return Err(failed(e, format!("Writing {} failed after {} bytes", temp.display(), counter.written)));
+    }
+    let Counter{inner, written} = counter;
+    let file = inner.into_inner().map_err(|e| failed(e.into_error(), format!("Writing {} failed after {written} bytes", temp.display())))?;
+
+    if let Ok(metadata) = fs::metadata(&target){
+        file.set_permissions(metadata.permissions()).map_err(|e| failed(e, format!("Can't copy the permissions of {filename}")))?;
+    }
+    file.sync_all().map_err(|e| failed(e, format!("Syncing {} failed after {written} bytes", temp.display())))?;
+    drop(file);
+
+    if backup && target.exists(){
+        let bak = backup_path(&target.to_string_lossy());
+        fs::copy(&target, &bak).map_err(|e| failed(e, format!("Can't keep the previous version in {bak}")))?;
+    }
+    fs::rename(&temp, &target).map_err(|e| failed(e, format!("Can't replace {filename} by {}", temp.display())))?;
+
+    // The rename itself is made durable by syncing the directory, where the platform allows it
+    if let Some(dir) = target.parent().filter(|dir| !dir.as_os_str().is_empty()){
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    Ok(())
+}
+
+/// `dir/.data.csv.<pid>.tmp`, in the directory of `target` so that the rename doesn't cross file systems
+fn temp_path(target: &Path) -> PathBuf{
+    let name = target.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    target.with_file_name(format!(".{name}.{}.tmp", process::id()))
+}
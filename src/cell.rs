@@ -1,30 +1,36 @@
 use std::fmt::Display;
-use crossterm::style::Color;
 
 use crate::Position;
+use crate::text;
+use crate::style::{NumberFormat, Style};
+use crate::temporal::Duration;
 
-#[derive(PartialEq,Clone, Debug)]
+use chrono::{NaiveDate, NaiveDateTime};
+
+#[derive(PartialEq,Clone, Debug, Default)]
 pub enum DataType{
     Int(i64),
     Float(f64),
     String(String),
     Bool(bool),
+    /// Date with the pattern it was read with
+    Date(NaiveDate, String),
+    DateTime(NaiveDateTime, String),
+    Duration(Duration),
+    #[default]
     Empty
 }
 
-impl Default for DataType{
-    fn default() -> Self {
-        DataType::Empty
-    }
-}
-
 impl Display for DataType{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(),std::fmt::Error> {
         match self{
-            DataType::String(s) => write!(f, "{}", s),
-            DataType::Int(i) =>  write!(f, "{}", i),
-            DataType::Float(fl) =>  write!(f, "{}", fl),
-            DataType::Bool(b) => write!(f, "{}", b),
+            DataType::String(s) => write!(f, "{s}"),
+            DataType::Int(i) =>  write!(f, "{i}"),
+            DataType::Float(fl) =>  write!(f, "{fl}"),
+            DataType::Bool(b) => write!(f, "{b}"),
+            DataType::Date(d, pattern) => write!(f, "{}", d.format(pattern)),
+            DataType::DateTime(d, pattern) => write!(f, "{}", d.format(pattern)),
+            DataType::Duration(d) => write!(f, "{d}"),
             DataType::Empty => write!(f, ""),
         }
     }
@@ -43,6 +49,7 @@ impl DataType{
                     5
                 }
             },
+            DataType::Date(..) | DataType::DateTime(..) | DataType::Duration(_) => self.to_string().len(),
             DataType::Empty => 0,
         }
     }
@@ -54,7 +61,7 @@ impl DataType{
                 if x.starts_with('='){
                     if let Ok(x) = x.parse::<f64>(){
                         self.switch_type(&DataType::Float(x));
-                    };
+                    }
                 }
             },
             DataType::Empty => {
@@ -66,10 +73,7 @@ impl DataType{
     }
 
     pub fn delete(&mut self, _at:usize){
-        match self{
-            DataType::String(x) => {x.pop();},
-            _ => ()
-        }
+        if let DataType::String(x) = self {text::pop_grapheme(x);}
     }
 
     fn switch_type(&mut self, nt: &DataType){
@@ -78,10 +82,59 @@ impl DataType{
             DataType::Int(_) => DataType::Int(i64::default()),
             DataType::Float(f) => DataType::Float(*f),
             DataType::String(_) => DataType::String(String::default()),
-            DataType::Empty => DataType::Empty,
+            DataType::Date(..) | DataType::DateTime(..) | DataType::Duration(_) | DataType::Empty => nt.clone(),
         };
     }
 
+    /// `text` as a value of the same type, or as text when it no longer reads as one
+    #[must_use]
+    pub fn with_text(&self, text: String) -> DataType{
+        match (self, text.trim()){
+            (DataType::Int(_), t) => t.parse().map_or(DataType::String(text), DataType::Int),
+            (DataType::Float(_), t) => t.parse().map_or(DataType::String(text), DataType::Float),
+            (DataType::Bool(_), t) => t.parse().map_or(DataType::String(text), DataType::Bool),
+            _ => DataType::String(text),
+        }
+    }
+
+    /// Text of a formula, starting with `=`
+    #[must_use]
+    pub fn formula(&self) -> Option<&str>{
+        match self{
+            DataType::String(s) if s.starts_with('=') => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Numeric value, text being parsed when it holds a number
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn as_number(&self) -> Option<f64>{
+        match self{
+            DataType::Int(i) => Some(*i as f64),
+            DataType::Float(f) => Some(*f),
+            DataType::String(s) => s.trim().parse::<f64>().ok(),
+            _ => None,
+        }
+    }
+
+    /// Seconds since 1970 of a date or a date-time, or length of a duration in seconds
+    #[must_use]
+    pub fn as_seconds(&self) -> Option<i64>{
+        match self{
+            DataType::Date(d, _) => d.and_hms_opt(0, 0, 0).map(|d| d.and_utc().timestamp()),
+            DataType::DateTime(d, _) => Some(d.and_utc().timestamp()),
+            DataType::Duration(d) => Some(d.delta.num_seconds()),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_temporal(&self) -> bool{
+        matches!(*self, DataType::Date(..) | DataType::DateTime(..) | DataType::Duration(_))
+    }
+
+    #[must_use]
     pub fn is_empy(&self) -> bool{
         *self == DataType::Empty
     }
@@ -105,13 +158,15 @@ impl DataType{
 pub struct Cell{
     pub val: DataType,
     pub pos: Position,
+    pub style: Style,
 }
 
 impl Default for Cell{
     fn default() -> Self {
         Self{
             val: DataType::Empty,
-            pos: Position{x:0,y:0}
+            pos: Position{x:0,y:0},
+            style: Style::default(),
         }
     }
 }
@@ -120,13 +175,17 @@ impl From<String> for Cell{
     fn from(val: String) -> Self {
         Self{
             val: DataType::String(val),
-            pos: Position{x:0,y:0}
+            pos: Position{x:0,y:0},
+            style: Style::default(),
         }
     }
 }
 
 impl Cell{
     pub fn insert(&mut self, c: char){
+        if self.val.is_temporal(){
+            self.val = DataType::String(self.val.to_string());
+        }
         match &self.val{
             DataType::String(_) => self.val.insert(c),
             DataType::Empty => {
@@ -137,32 +196,39 @@ impl Cell{
     }
 
     pub fn delete(&mut self, at:usize){
-        match &self.val{
-            DataType::String(_) => self.val.delete(at),
-            _ => (),
+        if self.val.is_temporal(){
+            self.val = DataType::String(self.val.to_string());
         }
+        if let DataType::String(_) = &self.val { self.val.delete(at) }
     }
 
     pub fn reset(&mut self){
-        self.val = DataType::Empty
+        self.val = DataType::Empty;
     }
 
+    /// Value as displayed, with the number format of the cell, cut to `max_len` cells when not 0
+    #[must_use]
     pub fn render(&self, max_len: usize) -> String{
-        let mut val = match &self.val{
+        self.render_as(self.style.format, max_len)
+    }
+
+    /// Value as displayed with `format`, cut to `max_len` cells when not 0.
+    /// Without format, floats are rounded to 15 significant digits so that `0.1 + 0.2` shows `0.3`.
+    #[must_use]
+    pub fn render_as(&self, format: Option<NumberFormat>, max_len: usize) -> String{
+        let max_len = if max_len == 0 { usize::MAX } else { max_len };
+        if let (Some(format), Some(n)) = (format, self.val.as_number()){
+            return text::truncate(&format.format(n), max_len);
+        }
+
+        let val = match &self.val{
             DataType::Int(s) => s.to_string(),
-            DataType::Float(s) => s.to_string(),
-            DataType::String(s) => s.to_string(),
+            DataType::Float(s) => format!("{s:.14e}").parse::<f64>().unwrap_or(*s).to_string(),
+            DataType::String(s) => s.clone(),
             DataType::Bool(s) => s.to_string(),
+            DataType::Date(..) | DataType::DateTime(..) | DataType::Duration(_) => self.val.to_string(),
             DataType::Empty => String::default(),
         };
-
-        if max_len == 0{
-            return val
-        }else if val.len() > max_len{
-            val.truncate(max_len.saturating_sub(2));
-            val.push_str("..");
-        }
-        // format!("{}{}", crossterm::style::SetForegroundColor(Color::Red), val)
-        val
+        text::truncate(&val, max_len)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,228 @@
+//! Conditional formats: styles given to the cells of a range
+//! depending on their value, checked each time the grid is drawn.
+
+use std::collections::{HashMap, HashSet};
+
+use crossterm::style::Color;
+
+use crate::cell::DataType;
+use crate::filter::Condition;
+use crate::reference::Range;
+use crate::style::{self, Style};
+use crate::Document;
+use crate::Position;
+
+pub enum Test{
+    /// Any filter expression, e.g. `<0`, `=done` or `/^ERR/`
+    Condition(Condition),
+    /// `count` highest (or lowest) numbers, or a percentage of them
+    Rank{count: f64, percent: bool, bottom: bool},
+    /// Values found more than once in the range
+    Duplicates,
+    /// Background going from `low` for the minimum to `high` for the maximum
+    Scale(Color, Color),
+}
+
+impl Test{
+    /// Parses `top 10%`, `bottom 5`, `duplicates`, `scale [low] [high]`, `contains x`,
+    /// anything else being read as a filter expression
+    ///
+    /// # Errors
+    /// Will return an error message when the expression can't be parsed
+    pub fn parse(expr: &str) -> Result<Self, String>{
+        let words: Vec<&str> = expr.split_whitespace().collect();
+        let test = match words.as_slice(){
+            ["duplicates"] => Test::Duplicates,
+            [rank @ ("top" | "bottom"), count] => {
+                let (count, percent) = count.strip_suffix('%').map_or((*count, false), |c| (c, true));
+                let count = count.parse::<f64>().map_err(|_| format!("Not a number: {count}"))?;
+                Test::Rank{count, percent, bottom: *rank == "bottom"}
+            },
+            ["scale", colors @ ..] if colors.len() <= 2 => {
+                let color = |i: usize, default: Color| colors.get(i).map_or(Some(default), |c| style::parse_color(c));
+                match (color(0, Color::Red), color(1, Color::Green)){
+                    (Some(low), Some(high)) => Test::Scale(low, high),
+                    _ => return Err(format!("Unknown color in: {expr}")),
+                }
+            },
+            _ => match expr.strip_prefix("contains "){
+                Some(text) => Test::Condition(Condition::Contains(text.to_lowercase())),
+                None => Test::Condition(Condition::parse(expr)?),
+            },
+        };
+        Ok(test)
+    }
+}
+
+pub struct Rule{
+    pub range: Range,
+    pub test: Test,
+    pub style: Style,
+    /// Rule as typed, to be saved
+    pub spec: String,
+}
+
+impl Rule{
+    /// Parses a rule such as `<0 -> fg=red`, `top 10% -> bold bg=green` or `scale red green`
+    ///
+    /// # Errors
+    /// Will return an error message when the test or the style can't be parsed
+    pub fn parse(range: Range, spec: &str) -> Result<Self, String>{
+        let (test, style_spec) = spec.split_once("->").unwrap_or((spec, ""));
+        let test = Test::parse(test.trim())?;
+
+        let mut style = Style::default();
+        style.apply(style_spec).map_err(|word| format!("Unknown style: {word}"))?;
+        if style.is_default() && !matches!(test, Test::Scale(..)){
+            return Err("Missing style, e.g. <0 -> fg=red".to_string());
+        }
+
+        Ok(Self{range, test, style, spec: spec.trim().to_string()})
+    }
+}
+
+/// Figures a rule needs from its whole range
+enum Bounds{
+    None,
+    Threshold(f64),
+    Repeated(HashSet<String>),
+    Span(f64, f64),
+}
+
+/// Rules of a document with their figures computed, ready to style the cells being drawn
+pub struct Formats<'a>{
+    rules: Vec<(&'a Rule, Bounds)>,
+}
+
+impl<'a> Formats<'a>{
+    #[must_use]
+    pub fn new(document: &'a Document) -> Self{
+        let rules = document.rules.iter().map(|rule| (rule, bounds(document, rule))).collect();
+        Self{rules}
+    }
+
+    /// Style given to the cell at `p` by the rules it passes, later rules taking precedence
+    #[must_use]
+    pub fn style(&self, p: &Position, val: &DataType) -> Style{
+        let mut result = Style::default();
+        for (rule, bounds) in self.rules.iter().filter(|(rule, _)| rule.range.contains(p)){
+            let number = val.as_number();
+            match (&rule.test, bounds){
+                (Test::Condition(condition), _) if condition.matches(val) => result.overlay(&rule.style),
+                (Test::Rank{bottom: false, ..}, Bounds::Threshold(t)) if number.is_some_and(|n| n >= *t) => result.overlay(&rule.style),
+                (Test::Rank{bottom: true, ..}, Bounds::Threshold(t)) if number.is_some_and(|n| n <= *t) => result.overlay(&rule.style),
+                (Test::Duplicates, Bounds::Repeated(values)) if values.contains(&val.to_string()) => result.overlay(&rule.style),
+                (Test::Scale(low, high), Bounds::Span(min, max)) => {
+                    if let Some(n) = number{
+                        let ratio = if max > min { (n - min) / (max - min) } else { 0.5 };
+                        result.overlay(&rule.style);
+                        result.bg = Some(style::blend(*low, *high, ratio));
+                    }
+                },
+                _ => (),
+            }
+        }
+        result
+    }
+}
+
+fn values<'a>(document: &'a Document, range: &Range) -> impl Iterator<Item = &'a DataType>{
+    let Range{start, end} = *range;
+    (start.y..=end.y.min(document.len.saturating_sub(1)))
+        .flat_map(move |y| (start.x..=end.x).map(move |x| Position{x, y}))
+        .filter_map(move |p| document.get_cell(&p))
+        .map(|cell| &cell.val)
+        .filter(|val| !val.to_string().trim().is_empty())
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+fn bounds(document: &Document, rule: &Rule) -> Bounds{
+    match rule.test{
+        Test::Condition(_) => Bounds::None,
+        Test::Rank{count, percent, bottom} => {
+            let mut numbers: Vec<f64> = values(document, &rule.range).filter_map(DataType::as_number).collect();
+            numbers.sort_by(|a, b| if bottom { a.total_cmp(b) } else { b.total_cmp(a) });
+            let count = if percent { (numbers.len() as f64 * count / 100.0).ceil() } else { count };
+            let count = (count.max(1.0) as usize).min(numbers.len());
+            numbers.get(count.wrapping_sub(1)).map_or(Bounds::None, |t| Bounds::Threshold(*t))
+        },
+        Test::Duplicates => {
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for val in values(document, &rule.range){
+                *counts.entry(val.to_string()).or_default() += 1;
+            }
+            Bounds::Repeated(counts.into_iter().filter(|(_, n)| *n > 1).map(|(val, _)| val).collect())
+        },
+        Test::Scale(..) => {
+            let numbers = values(document, &rule.range).filter_map(DataType::as_number);
+            numbers.fold(None, |span: Option<(f64, f64)>, n| Some(span.map_or((n, n), |(min, max)| (min.min(n), max.max(n)))))
+                .map_or(Bounds::None, |(min, max)| Bounds::Span(min, max))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::reference::parse_range;
+    use crate::Row;
+
+    /// Document of a single column holding `values`, with `rules` on all of it
+    fn document(values: &[&str], rules: &[&str]) -> Document{
+        let mut document = Document::default();
+        document.replace_rows(values.iter().map(|val| Row::parse(val, ';')).collect());
+        let range = parse_range(&format!("A1:A{}", values.len())).unwrap();
+        document.rules = rules.iter().map(|spec| Rule::parse(range, spec).unwrap()).collect();
+        document
+    }
+
+    /// Rows of the cells made bold by the rules
+    fn bold(document: &Document) -> Vec<usize>{
+        let formats = Formats::new(document);
+        (0..document.len).filter(|y| {
+            let p = Position{x: 0, y: *y};
+            formats.style(&p, &document.get_cell(&p).unwrap().val).bold
+        }).collect()
+    }
+
+    #[test]
+    fn rules_are_parsed(){
+        let range = parse_range("A1:B2").unwrap();
+        assert!(matches!(Test::parse("top 10%"), Ok(Test::Rank{percent: true, bottom: false, ..})));
+        assert!(matches!(Test::parse("bottom 5"), Ok(Test::Rank{percent: false, bottom: true, ..})));
+        assert!(matches!(Test::parse("scale"), Ok(Test::Scale(Color::Red, Color::Green))));
+        assert!(matches!(Test::parse("contains Late"), Ok(Test::Condition(Condition::Contains(text))) if text == "late"));
+        assert!(Test::parse("top x").is_err() && Test::parse("scale red nocolor").is_err());
+        assert_eq!(Rule::parse(range, " <0 -> fg=red ").unwrap().spec, "<0 -> fg=red");
+        assert!(Rule::parse(range, "scale blue red").is_ok());
+        assert!(Rule::parse(range, "<0").is_err() && Rule::parse(range, "<0 -> shiny").is_err());
+    }
+
+    #[test]
+    fn conditions_and_ranks_style_the_matching_cells(){
+        let values = ["5", "-1", "12", "x", "", "7"];
+        assert_eq!(bold(&document(&values, &["<0 -> bold"])), [1]);
+        assert_eq!(bold(&document(&values, &["top 2 -> bold"])), [2, 5]);
+        assert_eq!(bold(&document(&values, &["bottom 25% -> bold"])), [1]);
+        assert_eq!(bold(&document(&values, &["top 0 -> bold"])), [2]);
+        assert_eq!(bold(&document(&values, &["contains X -> bold"])), [3]);
+    }
+
+    #[test]
+    fn duplicates_ignore_blanks(){
+        assert_eq!(bold(&document(&["a", "", "b", "a", "", "1", "1.0"], &["duplicates -> bold"])), [0, 3]);
+    }
+
+    #[test]
+    fn later_rules_take_precedence_and_scales_blend(){
+        let document = document(&["0", "5", "10"], &["<10 -> fg=red bold", ">0 -> fg=blue", "scale red green"]);
+        let formats = Formats::new(&document);
+        let style = |y: usize| formats.style(&Position{x: 0, y}, &document.get_cell(&Position{x: 0, y}).unwrap().val);
+        assert!(style(0).fg == Some(Color::Red) && style(0).bold);
+        assert!(style(1).fg == Some(Color::Blue) && style(1).bold);
+        assert!(style(2).fg == Some(Color::Blue) && !style(2).bold);
+        assert!(style(0).bg == Some(style::blend(Color::Red, Color::Green, 0.0)));
+        assert!(style(1).bg == Some(style::blend(Color::Red, Color::Green, 0.5)));
+        assert!(formats.style(&Position{x: 1, y: 0}, &DataType::Int(0)).is_default());
+    }
+}
@@ -0,0 +1,265 @@
+//! Differences between two documents, their rows being paired by a key column or by position.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+
+use crossterm::style::Color;
+
+use crate::reference::column_name;
+use crate::Document;
+use crate::Position;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Side{
+    Old,
+    New,
+}
+
+#[derive(Clone, Copy)]
+pub enum Pairing{
+    /// Rows having the same value in this column are paired
+    Key(usize),
+    /// Rows are paired by number
+    Position,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Kind{
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Row found on one side only, or pair of rows whose cells differ
+pub struct Line{
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+    /// Columns whose values differ
+    pub columns: Vec<usize>,
+}
+
+impl Line{
+    #[must_use]
+    pub fn kind(&self) -> Kind{
+        match (self.old, self.new){
+            (None, _) => Kind::Added,
+            (_, None) => Kind::Removed,
+            _ => Kind::Changed,
+        }
+    }
+
+    /// Background of the cell in the column `x`, if it differs
+    #[must_use]
+    pub fn color(&self, x: usize) -> Option<Color>{
+        match self.kind(){
+            Kind::Added => Some(Color::Green),
+            Kind::Removed => Some(Color::Red),
+            Kind::Changed => self.columns.contains(&x).then_some(Color::Yellow),
+        }
+    }
+
+    /// Sign put before the row number
+    #[must_use]
+    pub fn marker(&self) -> char{
+        match self.kind(){
+            Kind::Added => '+',
+            Kind::Removed => '-',
+            Kind::Changed => '~',
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Summary{
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub cells: usize,
+}
+
+impl Display for Summary{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        write!(f, "{} added, {} removed, {} changed row(s) ({} cell(s))", self.added, self.removed, self.changed, self.cells)
+    }
+}
+
+pub struct Diff{
+    /// Differences in the order of the new document, removed rows coming before the row paired after them
+    pub lines: Vec<Line>,
+    old_rows: HashMap<usize, usize>,
+    new_rows: HashMap<usize, usize>,
+}
+
+impl Diff{
+    /// Compares `old` to `new`, the first `fixed` rows (headers) being always paired together
+    #[must_use]
+    pub fn new(old: &Document, new: &Document, pairing: Pairing, fixed: usize) -> Self{
+        let pairs = pair(old, new, pairing, fixed);
+        let columns = old.col_count().max(new.col_count());
+
+        let mut paired = vec![false; old.len];
+        for o in pairs.values(){
+            paired[*o] = true;
+        }
+        let mut removed = (0..old.len).filter(|o| !paired[*o]).peekable();
+
+        let mut lines = Vec::new();
+        for n in 0..new.len{
+            let Some(&o) = pairs.get(&n) else{
+                lines.push(Line{old: None, new: Some(n), columns: Vec::new()});
+                continue;
+            };
+            while let Some(r) = removed.next_if(|r| *r < o){
+                lines.push(Line{old: Some(r), new: None, columns: Vec::new()});
+            }
+            let changed: Vec<usize> = (0..columns).filter(|x| value(old, *x, o) != value(new, *x, n)).collect();
+            if !changed.is_empty(){
+                lines.push(Line{old: Some(o), new: Some(n), columns: changed});
+            }
+        }
+        lines.extend(removed.map(|r| Line{old: Some(r), new: None, columns: Vec::new()}));
+
+        let mut diff = Self{lines, old_rows: HashMap::new(), new_rows: HashMap::new()};
+        diff.index();
+        diff
+    }
+
+    fn index(&mut self){
+        for (i, line) in self.lines.iter().enumerate(){
+            if let Some(o) = line.old{
+                self.old_rows.insert(o, i);
+            }
+            if let Some(n) = line.new{
+                self.new_rows.insert(n, i);
+            }
+        }
+    }
+
+    /// Difference involving the row `y` of a side
+    #[must_use]
+    pub fn line(&self, side: Side, y: usize) -> Option<&Line>{
+        let rows = if side == Side::Old { &self.old_rows } else { &self.new_rows };
+        rows.get(&y).map(|i| &self.lines[*i])
+    }
+
+    #[must_use]
+    pub fn summary(&self) -> Summary{
+        let mut summary = Summary::default();
+        for line in &self.lines{
+            match line.kind(){
+                Kind::Added => summary.added += 1,
+                Kind::Removed => summary.removed += 1,
+                Kind::Changed => {
+                    summary.changed += 1;
+                    summary.cells += line.columns.len();
+                },
+            }
+        }
+        summary
+    }
+
+    /// Differences one per line, `-`, `+` or `~` then the row numbers and the values, followed by the summary
+    #[must_use]
+    pub fn report(&self, old: &Document, new: &Document) -> String{
+        let mut report = String::new();
+        for line in &self.lines{
+            let text = match (line.old, line.new){
+                (Some(o), None) => format!("- {}: {}", o + 1, row_text(old, o)),
+                (None, Some(n)) => format!("+ {}: {}", n + 1, row_text(new, n)),
+                (Some(o), Some(n)) => {
+                    let cells: Vec<String> = line.columns.iter()
+                        .map(|x| format!("{}: {} -> {}", column_name(*x), value(old, *x, o), value(new, *x, n)))
+                        .collect();
+                    format!("~ {} -> {}: {}", o + 1, n + 1, cells.join(", "))
+                },
+                (None, None) => continue,
+            };
+            report.push_str(&text);
+            report.push('\n');
+        }
+        report.push_str(&self.summary().to_string());
+        report.push('\n');
+        report
+    }
+}
+
+fn value(document: &Document, x: usize, y: usize) -> String{
+    document.get_cell(&Position{x, y}).map(|cell| cell.val.to_string()).unwrap_or_default()
+}
+
+fn row_text(document: &Document, y: usize) -> String{
+    document.rows[y].cells.iter().map(|cell| cell.val.to_string()).collect::<Vec<String>>().join(";")
+}
+
+/// Row of `old` paired with each paired row of `new`
+fn pair(old: &Document, new: &Document, pairing: Pairing, fixed: usize) -> HashMap<usize, usize>{
+    let fixed = fixed.min(old.len).min(new.len);
+    let mut pairs: HashMap<usize, usize> = (0..fixed).map(|y| (y, y)).collect();
+    match pairing{
+        Pairing::Position => pairs.extend((fixed..old.len.min(new.len)).map(|y| (y, y))),
+        Pairing::Key(x) => {
+            // Rows sharing a key are paired in order
+            let mut rows: HashMap<String, VecDeque<usize>> = HashMap::new();
+            for o in fixed..old.len{
+                rows.entry(value(old, x, o)).or_default().push_back(o);
+            }
+            for n in fixed..new.len{
+                if let Some(o) = rows.get_mut(&value(new, x, n)).and_then(VecDeque::pop_front){
+                    pairs.insert(n, o);
+                }
+            }
+        },
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::Row;
+
+    fn document(text: &str) -> Document{
+        let mut document = Document::default();
+        document.replace_rows(text.lines().map(|line| Row::parse(line, ';')).collect());
+        document
+    }
+
+    fn report(old: &str, new: &str, pairing: Pairing, fixed: usize) -> String{
+        let (old, new) = (document(old), document(new));
+        Diff::new(&old, &new, pairing, fixed).report(&old, &new)
+    }
+
+    #[test]
+    fn rows_are_paired_by_position(){
+        assert_eq!(report("a;1\nb;2", "a;1\nb;2", Pairing::Position, 0), "0 added, 0 removed, 0 changed row(s) (0 cell(s))\n");
+        assert_eq!(
+            report("a;1\nb;2\nc;3", "a;1\nb;9", Pairing::Position, 0),
+            "~ 2 -> 2: B: 2 -> 9\n- 3: c;3\n0 added, 1 removed, 1 changed row(s) (1 cell(s))\n",
+        );
+        assert_eq!(
+            report("a;1", "a;1;x\nb", Pairing::Position, 0),
+            "~ 1 -> 1: C:  -> x\n+ 2: b\n1 added, 0 removed, 1 changed row(s) (1 cell(s))\n",
+        );
+    }
+
+    #[test]
+    fn rows_are_paired_by_key_in_order(){
+        assert_eq!(
+            report("id;n\n1;a\n2;b\n3;c", "id;n\n3;c\n1;x\n4;d", Pairing::Key(0), 1),
+            "- 3: 2;b\n~ 2 -> 3: B: a -> x\n+ 4: 4;d\n1 added, 1 removed, 1 changed row(s) (1 cell(s))\n",
+        );
+        // Repeated keys pair the first with the first
+        assert_eq!(report("k;1\nk;2", "k;1\nk;2", Pairing::Key(0), 0), "0 added, 0 removed, 0 changed row(s) (0 cell(s))\n");
+        // Headers are paired whatever their key
+        assert_eq!(report("id\n1", "ID\n1", Pairing::Key(0), 1), "~ 1 -> 1: A: id -> ID\n0 added, 0 removed, 1 changed row(s) (1 cell(s))\n");
+    }
+
+    #[test]
+    fn lines_are_found_from_either_side(){
+        let (old, new) = (document("a\nb\nc"), document("a\nc\nd"));
+        let diff = Diff::new(&old, &new, Pairing::Key(0), 0);
+        assert!(diff.line(Side::Old, 1).is_some_and(|line| line.kind() == Kind::Removed && line.marker() == '-'));
+        assert!(diff.line(Side::New, 2).is_some_and(|line| line.kind() == Kind::Added && line.color(5) == Some(Color::Green)));
+        assert!(diff.line(Side::Old, 0).is_none() && diff.line(Side::New, 1).is_none());
+    }
+}
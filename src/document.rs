@@ -2,59 +2,176 @@ use crate::Row;
 use crate::Cell;
 use crate::Position;
 use crate::SearchDirection;
+use crate::Edit;
+use crate::history::{Column, Ranges};
+use crate::sort::{self, SortKey};
+use crate::filter::Filters;
+use crate::sidecar;
+use crate::atomic;
+use crate::text;
+use crate::reference::Range;
+use crate::conditional::Rule;
+use crate::style::NumberFormat;
+use crate::temporal;
+use crate::formula;
+use crate::cell::DataType;
 
-use std::fs::{self, File};
-use std::io::Write;
+use std::fs;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::BTreeMap;
+
+pub const DEFAULT_WIDTH: usize = 9;
+pub const MIN_WIDTH: usize = 3;
+pub const MAX_WIDTH: usize = 80;
+
+/// Last revision given to a document, revisions being unique across documents
+static REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64{
+    REVISION.fetch_add(1, Ordering::Relaxed) + 1
+}
 
 #[derive(Default)]
 pub struct Document{
     pub rows: Vec<Row>,
     pub file_name: Option<String>,
     pub len: usize,
+    pub filters: Filters,
+    widths: Vec<usize>,
+    /// Number formats of the columns, used by the cells without one
+    formats: Vec<Option<NumberFormat>>,
+    /// Named ranges, by upper case name
+    pub names: BTreeMap<String, Range>,
+    /// Conditional formats, in the order they are applied
+    pub rules: Vec<Rule>,
+    /// Date patterns (`%d/%m/%Y`) tried after the ISO 8601 ones
+    pub patterns: Vec<String>,
+    /// Sheets of the workbook, listed in the sidecar of its first sheet
+    pub sheet_names: Vec<String>,
+    /// Whether the document changed since it was opened or saved
+    dirty: bool,
+    /// Changes with the content, two documents only sharing one while empty
+    revision: u64,
 }
 
 impl Document{
 
-    // #Errors
-    // Will return an error when it can't read the file to a string
+    /// # Errors
+    /// Will return an error when it can't read the file to a string
     pub fn open(filename: &str) -> Result<Self, std::io::Error>{
         let content = fs::read_to_string(filename)?;
         let mut rows: Vec<Row> = Vec::new();
         let mut len: usize = 0;
         for row in content.lines(){
-            let cells: Vec<Cell> = row.split(';').map(|s| Cell::from(s.to_string())).collect();
-            let row_len = cells.len();
-            rows.push(Row{cells, len: row_len});
+            rows.push(Row::parse(row, ';'));
             len+=1;
         }
 
-        Ok(Self{
+        let mut document = Self{
             rows,
             file_name: Some(filename.to_string()),
             len,
-        })
+            filters: Filters::default(),
+            widths: Vec::new(),
+            formats: Vec::new(),
+            names: BTreeMap::new(),
+            rules: Vec::new(),
+            patterns: Vec::new(),
+            sheet_names: Vec::new(),
+            dirty: false,
+            revision: next_revision(),
+        };
+        sidecar::load(filename, &mut document);
+        document.detect_temporal();
+        document.dirty = false;
+        Ok(document)
     }
 
-    // #Errors
-    // Will return an error when writing the file to the disk fail
-    pub fn save(&mut self) -> Result<(), std::io::Error>{
+    /// Replaces the file by the document, keeping a copy of the previous version with `backup`
+    ///
+    /// # Errors
+    /// Will return an error when writing the file to the disk fail, the previous version being kept
+    pub fn save(&mut self, backup: bool) -> Result<(), std::io::Error>{
         if let Some(filename) = &self.file_name{
-            let mut file = File::create(filename)?;
-            for row in &self.rows{
-                file.write_all(row.stringify(";").as_bytes())?;
-                file.write_all(b"\n")?;
-            }
+            write_rows(filename, backup, self.rows.iter())?;
+            sidecar::save(filename, self)?;
+            self.dirty = false;
         }
 
         Ok(())
     }
 
+    #[must_use]
+    pub fn is_dirty(&self) -> bool{
+        self.dirty
+    }
+
+    fn changed(&mut self){
+        self.dirty = true;
+        self.revision = next_revision();
+    }
+
+    /// Changes with every edit, so that an unchanged revision tells the document wasn't touched
+    #[must_use]
+    pub fn revision(&self) -> u64{
+        self.revision
+    }
+
+    /// Marks the document as saved, once written by someone else
+    pub fn mark_saved(&mut self){
+        self.dirty = false;
+    }
+
+    /// Writes only the rows passing the filters to `filename`, with the styles of their cells,
+    /// the first `fixed` rows being always kept
+    ///
+    /// # Errors
+    /// Will return an error when writing the file to the disk fail
+    pub fn export_visible(&self, filename: &str, fixed: usize) -> Result<(), std::io::Error>{
+        let rows: Vec<Row> = self.rows.iter().enumerate().filter(|(y, _)| *y < fixed || self.row_visible(*y)).map(|(_, row)| row.clone()).collect();
+        write_rows(filename, false, rows.iter())?;
+
+        // The styles go with their rows, the columns keep their widths and formats
+        let mut exported = Self{rows, widths: self.widths.clone(), formats: self.formats.clone(), ..Self::default()};
+        exported.update_len();
+        sidecar::save(filename, &exported)
+    }
+
+    /// Rows past the end of the document are always visible
+    #[must_use]
+    pub fn row_visible(&self, y: usize) -> bool{
+        self.rows.get(y).is_none_or(|row| self.filters.matches(row))
+    }
+
     pub fn insert(&mut self, at: &Position, c: char){
         if self.rows.len() <= at.y{
-            self.fill(at.y.saturating_sub(self.len).saturating_add(1))
+            self.fill(at.y.saturating_sub(self.len).saturating_add(1));
         }
 
-        self.rows[at.y].insert(c, at.x)
+        self.rows[at.y].insert(c, at.x);
+        self.detect_cell(at);
+        self.changed();
+    }
+
+    /// Turns the text holding a date, a date-time or a duration into its value
+    pub fn detect_temporal(&mut self){
+        for y in 0..self.rows.len(){
+            for x in 0..self.rows[y].cells.len(){
+                self.detect_cell(&Position{x, y});
+            }
+        }
+    }
+
+    fn detect_cell(&mut self, at: &Position){
+        let Some(cell) = self.rows.get_mut(at.y).and_then(|row| row.cells.get_mut(at.x)) else{
+            return;
+        };
+        if let DataType::String(s) = &cell.val{
+            if let Some(val) = temporal::parse(s, &self.patterns){
+                cell.val = val;
+            }
+        }
     }
 
     pub fn insert_cell(&mut self, at: &Position, cell: &Cell){
@@ -62,6 +179,7 @@ impl Document{
             self.fill(at.y.saturating_sub(self.len).saturating_add(1));
         }
         self.rows[at.y].insert_cell(at.x, cell);
+        self.changed();
     }
 
     pub fn delete(&mut self, at:&Position){
@@ -69,57 +187,127 @@ impl Document{
             return;
         }
 
-        self.rows[at.y].delete(at.x)
+        self.rows[at.y].delete(at.x);
+        self.detect_cell(at);
+        self.changed();
+    }
+
+    /// Empties the cell at `at`, keeping its style
+    pub fn clear_cell(&mut self, at: &Position){
+        if let Some(cell) = self.rows.get_mut(at.y).and_then(|row| row.cells.get_mut(at.x)){
+            cell.reset();
+            self.changed();
+        }
+    }
+
+    /// Adds the rows another program appended to the file, which leaves the document clean,
+    /// the first one replacing the last row when it completes it
+    pub fn append(&mut self, rows: Vec<Row>, replace_last: bool){
+        if replace_last{
+            self.rows.pop();
+        }
+        let from = self.rows.len();
+        self.rows.extend(rows);
+        self.update_len();
+        self.revision = next_revision();
+        for y in from..self.len{
+            for x in 0..self.rows[y].cells.len(){
+                self.detect_cell(&Position{x, y});
+            }
+        }
+    }
+
+    /// Replaces every row, as when recovering them
+    pub fn replace_rows(&mut self, rows: Vec<Row>){
+        self.rows = rows;
+        self.update_len();
+        self.detect_temporal();
+        self.changed();
+    }
+
+    /// Replaces the values with the ones of `rows`, recovered from a swap file, each cell
+    /// keeping its style and, when the recovered text still fits it, its type
+    pub fn recover_rows(&mut self, mut rows: Vec<Row>){
+        for (y, row) in rows.iter_mut().enumerate(){
+            for (x, cell) in row.cells.iter_mut().enumerate(){
+                if let Some(old) = self.get_cell(&Position{x, y}){
+                    cell.val = old.val.with_text(cell.val.to_string());
+                    cell.style = old.style.clone();
+                }
+            }
+        }
+        self.replace_rows(rows);
     }
 
+    /// Position of the next cell containing `query`, starting at `curr_position` included.
+    /// Hidden rows are skipped and the search wraps around the document.
+    #[must_use]
     pub fn find(&self, query: &str, curr_position: &Position, direction: SearchDirection) -> Option<Position>{
-        if curr_position.y > self.len{
-            return None
+        if self.len == 0 || query.is_empty(){
+            return None;
         }
 
-        let mut position = Position{x: curr_position.x, y:curr_position.y};
-        
-        let start = if direction == SearchDirection::Forward {
-            curr_position.y
-        } else {
-            0
-        };
-        let end = if direction == SearchDirection::Forward {
-            self.len
-        } else {
-            curr_position.y.saturating_add(1)
+        let (start, at) = if curr_position.y < self.len{
+            (curr_position.y, curr_position.x)
+        }else if direction == SearchDirection::Forward{
+            (0, 0)
+        }else{
+            (self.len - 1, usize::MAX)
         };
-        for _ in start..end {
-            if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.find(&query, position.x, direction) {
-                    position.x = x;
-                    return Some(position);
-                }
-                if direction == SearchDirection::Forward {
-                    position.y = position.y.saturating_add(1);
-                    position.x = 0;
-                } else {
-                    position.y = position.y.saturating_sub(1);
-                    position.x = self.rows[position.y].len;
-                }
-            } else {
-                return None;
+
+        // The starting row is visited twice, to find the cells before `at` once wrapped
+        for i in 0..=self.len{
+            let y = if direction == SearchDirection::Forward{
+                (start + i) % self.len
+            }else{
+                (start + self.len - i % self.len) % self.len
+            };
+            let x = match (i, direction){
+                (0, _) => at,
+                (_, SearchDirection::Forward) => 0,
+                (_, SearchDirection::Backward) => usize::MAX,
+            };
+
+            if !self.row_visible(y){
+                continue;
+            }
+            if let Some(x) = self.rows[y].find(query, x, direction){
+                return Some(Position{x, y});
             }
         }
         None
     }
 
+    /// Positions of every visible cell containing `query`, row by row
+    #[must_use]
+    pub fn find_all(&self, query: &str) -> Vec<Position>{
+        let mut found = Vec::new();
+        if query.is_empty(){
+            return found;
+        }
+
+        for (y, row) in self.rows.iter().enumerate().filter(|(y, _)| self.row_visible(*y)){
+            for (x, cell) in row.cells.iter().enumerate(){
+                if cell.val.to_string().contains(query){
+                    found.push(Position{x, y});
+                }
+            }
+        }
+        found
+    }
+
     pub fn fill(&mut self, n: usize){
         for _ in 0..n{
             self.add_row();
         }
-        self.update_len()
+        self.update_len();
     }
 
     fn update_len(&mut self){
-        self.len = self.rows.len()
+        self.len = self.rows.len();
     }
 
+    #[must_use]
     pub fn col_count(&self) -> usize{
         let max = self.rows.iter().map(|r| r.cells.len()).max().unwrap_or(0);
         max
@@ -135,17 +323,349 @@ impl Document{
 
             self.rows[i].cells.push(Cell::default());
         }
+        self.changed();
     }
 
-    pub fn del_col(&mut self, at: usize){
-        for i in 0..self.len{
-            let row_len = self.rows[i].len;
-            if at <= row_len{
-                self.rows[i].cells.remove(at);
+    /// Removes the column `at` with its width and format, `None` when no row reaches it
+    pub fn del_col(&mut self, at: usize) -> Option<Column>{
+        if self.rows.iter().all(|row| row.len <= at){
+            return None;
+        }
+        let (width, format) = (self.col_width(at), self.col_format(at));
+        if at < self.widths.len(){
+            self.widths.remove(at);
+        }
+        if at < self.formats.len(){
+            self.formats.remove(at);
+        }
+        self.changed();
+        let cells = self.rows.iter_mut().map(|row| row.remove_column(at)).collect();
+        let filter = self.filters.remove_column(at);
+        let ranges = self.move_ranges(|range| range.column_deleted(at));
+        Some(Column{cells, width, format, filter, ranges})
+    }
+
+    /// Puts back a column removed by `del_col`
+    pub fn restore_col(&mut self, at: usize, column: Column){
+        self.restore_ranges(column.ranges);
+        for (row, cell) in self.rows.iter_mut().zip(column.cells){
+            if let Some(cell) = cell{
+                row.restore_column(at, cell);
+            }
+        }
+        if at <= self.widths.len(){
+            self.widths.insert(at, column.width);
+        }else if column.width != DEFAULT_WIDTH{
+            self.set_col_width(at, column.width);
+        }
+        if at <= self.formats.len(){
+            self.formats.insert(at, column.format);
+        }else if column.format.is_some(){
+            self.set_col_format(at, column.format);
+        }
+
+        self.filters.insert_column(at);
+        if let Some(filter) = column.filter{
+            self.filters.set(filter);
+        }
+        self.changed();
+    }
+
+    /// Moves the named ranges, the conditional formats and the references of the formulas as a row or
+    /// a column is inserted or deleted, `moved` giving where a range goes, `None` once its cells are
+    /// all deleted. Returns what changed, for `restore_ranges` to put it back
+    fn move_ranges<F>(&mut self, moved: F) -> Ranges where F: Fn(Range) -> Option<Range>{
+        let mut ranges = Ranges::default();
+        for (name, range) in mem::take(&mut self.names){
+            let target = moved(range);
+            if target != Some(range){
+                ranges.names.push((name.clone(), range));
+            }
+            if let Some(target) = target{
+                self.names.insert(name, target);
+            }
+        }
+
+        for (i, mut rule) in mem::take(&mut self.rules).into_iter().enumerate(){
+            let range = rule.range;
+            let Some(target) = moved(range) else{
+                ranges.dropped_rules.push((i, rule));
+                continue;
+            };
+            if target != range{
+                ranges.moved_rules.push((i, range));
+            }
+            rule.range = target;
+            self.rules.push(rule);
+        }
+
+        for (y, row) in self.rows.iter_mut().enumerate(){
+            for (x, cell) in row.cells.iter_mut().enumerate(){
+                let DataType::String(text) = &mut cell.val else{
+                    continue;
+                };
+                if !text.starts_with('='){
+                    continue;
+                }
+                if let Some(rewritten) = formula::move_references(text, &moved){
+                    ranges.formulas.push((Position{x, y}, mem::replace(text, rewritten)));
+                }
+            }
+        }
+        ranges
+    }
+
+    /// Puts back what `move_ranges` changed for a deletion, before the deleted row or column is
+    /// put back itself
+    fn restore_ranges(&mut self, ranges: Ranges){
+        self.names.extend(ranges.names);
+        // Dropped rules go back in order, before the moved ones get their range back
+        for (i, rule) in ranges.dropped_rules{
+            self.rules.insert(i.min(self.rules.len()), rule);
+        }
+        for (i, range) in ranges.moved_rules{
+            if let Some(rule) = self.rules.get_mut(i){
+                rule.range = range;
+            }
+        }
+        for (p, text) in ranges.formulas{
+            if let Some(cell) = self.rows.get_mut(p.y).and_then(|row| row.cells.get_mut(p.x)){
+                cell.val = DataType::String(text);
             }
         }
     }
 
+    /// Shifts every column from `at` one step to the right
+    pub fn insert_col(&mut self, at: usize) -> bool{
+        let mut inserted = false;
+        for row in &mut self.rows{
+            inserted |= row.insert_column(at);
+        }
+        if !inserted{
+            return false;
+        }
+        if at < self.widths.len(){
+            self.widths.insert(at, DEFAULT_WIDTH);
+        }
+        if at < self.formats.len(){
+            self.formats.insert(at, None);
+        }
+        self.filters.insert_column(at);
+        self.move_ranges(|range| Some(range.column_inserted(at)));
+        self.changed();
+        true
+    }
+
+    #[must_use]
+    pub fn col_width(&self, x: usize) -> usize{
+        self.widths.get(x).copied().unwrap_or(DEFAULT_WIDTH)
+    }
+
+    pub fn set_col_width(&mut self, x: usize, width: usize){
+        if self.widths.len() <= x{
+            self.widths.resize(x.saturating_add(1), DEFAULT_WIDTH);
+        }
+        self.widths[x] = width.clamp(MIN_WIDTH, MAX_WIDTH);
+        self.changed();
+    }
+
+    #[must_use]
+    pub fn col_format(&self, x: usize) -> Option<NumberFormat>{
+        self.formats.get(x).copied().flatten()
+    }
+
+    pub fn set_col_format(&mut self, x: usize, format: Option<NumberFormat>){
+        if self.formats.len() <= x{
+            self.formats.resize(x.saturating_add(1), None);
+        }
+        self.formats[x] = format;
+        self.changed();
+    }
+
+    /// Columns having a number format
+    pub fn custom_formats(&self) -> impl Iterator<Item = (usize, NumberFormat)> + '_{
+        self.formats.iter().enumerate().filter_map(|(x, format)| format.map(|f| (x, f)))
+    }
+
+    /// Value of the cell as displayed, with its own number format or the one of its column
+    #[must_use]
+    pub fn render_cell(&self, cell: &Cell, x: usize, max_len: usize) -> String{
+        let format = cell.style.format.or(self.col_format(x));
+        // A formula that can be computed shows its value
+        if let Some(val) = cell.val.formula().and_then(|text| formula::evaluate(self, text)){
+            return Cell{val, pos: cell.pos, style: cell.style.clone()}.render_as(format, max_len);
+        }
+        cell.render_as(format, max_len)
+    }
+
+    /// Fits the column to its widest value, in terminal cells
+    pub fn auto_fit(&mut self, x: usize){
+        let widest = self.rows.iter()
+            .filter_map(|row| row.cells.get(x))
+            .map(|cell| text::width(&self.render_cell(cell, x, 0)))
+            .max()
+            .unwrap_or(0);
+        self.set_col_width(x, widest.saturating_add(1));
+    }
+
+    pub fn define_name(&mut self, name: &str, range: Range){
+        self.names.insert(name.to_uppercase(), range);
+        self.changed();
+    }
+
+    /// Reads dates with `pattern` too, from now on
+    pub fn add_pattern(&mut self, pattern: String){
+        self.patterns.push(pattern);
+        self.detect_temporal();
+        self.changed();
+    }
+
+    pub fn add_rule(&mut self, rule: Rule){
+        self.rules.push(rule);
+        self.changed();
+    }
+
+    /// Removes the conditional formats covering `p`, returns how many were
+    pub fn remove_rules_at(&mut self, p: &Position) -> usize{
+        let before = self.rules.len();
+        self.rules.retain(|rule| !rule.range.contains(p));
+        let removed = before - self.rules.len();
+        if removed > 0{
+            self.changed();
+        }
+        removed
+    }
+
+    #[must_use]
+    pub fn named_range(&self, name: &str) -> Option<Range>{
+        self.names.get(&name.trim().to_uppercase()).copied()
+    }
+
+    /// Columns whose width differs from the default one
+    pub fn custom_widths(&self) -> impl Iterator<Item = (usize, usize)> + '_{
+        self.widths.iter().copied().enumerate().filter(|(_, w)| *w != DEFAULT_WIDTH)
+    }
+
+    pub fn insert_row(&mut self, at: usize, row: Row){
+        if self.len < at{
+            self.fill(at.saturating_sub(self.len));
+        }
+
+        self.rows.insert(at, row);
+        self.update_len();
+        self.move_ranges(|range| Some(range.row_inserted(at)));
+        self.changed();
+    }
+
+    /// Removes the row `at`, with what its deletion changed elsewhere
+    pub fn del_row(&mut self, at: usize) -> Option<(Row, Ranges)>{
+        if self.len <= at{
+            return None;
+        }
+
+        let row = self.rows.remove(at);
+        self.update_len();
+        let ranges = self.move_ranges(|range| range.row_deleted(at));
+        self.changed();
+        Some((row, ranges))
+    }
+
+    /// Puts back a row removed by `del_row`
+    pub fn restore_row(&mut self, at: usize, row: Row, ranges: Ranges){
+        self.restore_ranges(ranges);
+        if self.len < at{
+            self.fill(at - self.len);
+        }
+        self.rows.insert(at, row);
+        self.update_len();
+        self.changed();
+    }
+
+    /// Order of the rows sorted by `keys`, the first `fixed` rows staying in place
+    #[must_use]
+    pub fn sort_order(&self, keys: &[SortKey], fixed: usize) -> Vec<usize>{
+        let fixed = fixed.min(self.len);
+        let sorted = sort::permutation(&self.rows[fixed..], keys);
+        (0..fixed).chain(sorted.into_iter().map(|i| i + fixed)).collect()
+    }
+
+    /// Applies an edit and returns the edit reverting it,
+    /// or `None` when the edit didn't change the document
+    pub fn apply(&mut self, edit: Edit) -> Option<Edit>{
+        match edit{
+            Edit::InsertRow(at) => {
+                if self.len < at{
+                    return None;
+                }
+                self.insert_row(at, Row::default());
+                Some(Edit::DeleteRow(at))
+            },
+            Edit::RestoreRow(at, row, ranges) => {
+                self.restore_row(at, row, ranges);
+                Some(Edit::DeleteRow(at))
+            },
+            Edit::DeleteRow(at) => self.del_row(at).map(|(row, ranges)| Edit::RestoreRow(at, row, ranges)),
+            Edit::InsertColumn(at) => {
+                if self.insert_col(at){
+                    Some(Edit::DeleteColumn(at))
+                }else{
+                    None
+                }
+            },
+            Edit::RestoreColumn(at, column) => {
+                self.restore_col(at, column);
+                Some(Edit::DeleteColumn(at))
+            },
+            Edit::DeleteColumn(at) => self.del_col(at).map(|column| Edit::RestoreColumn(at, column)),
+            Edit::Reorder(order) => {
+                // The order covers the rows there were when it was made, rows added since stay after them
+                if order.len() > self.rows.len() || order.iter().any(|from| *from >= order.len()) || order.iter().enumerate().all(|(i, from)| i == *from){
+                    return None;
+                }
+                let mut inverse = vec![0; order.len()];
+                let mut old: Vec<Option<Row>> = mem::take(&mut self.rows).into_iter().map(Some).collect();
+                for (i, from) in order.iter().enumerate(){
+                    self.rows.push(old[*from].take().unwrap_or_default());
+                    inverse[*from] = i;
+                }
+                self.rows.extend(old.into_iter().skip(order.len()).flatten());
+                self.update_len();
+                self.changed();
+                Some(Edit::Reorder(inverse))
+            },
+            Edit::ReplaceRows(at, count, rows) => {
+                if at + count > self.rows.len(){
+                    return None;
+                }
+                let inserted = rows.len();
+                let previous = self.rows.splice(at..at + count, rows).collect();
+                self.update_len();
+                self.detect_temporal();
+                self.changed();
+                Some(Edit::ReplaceRows(at, inserted, previous))
+            },
+            Edit::SetCells(cells) => {
+                let mut previous = Vec::with_capacity(cells.len());
+                for (p, cell) in cells{
+                    previous.push((p, self.get_cell(&p).cloned().unwrap_or_default()));
+                    self.insert_cell(&p, &cell);
+                    self.detect_cell(&p);
+                }
+                Some(Edit::SetCells(previous))
+            },
+            Edit::SetStyles(styles) => {
+                let mut previous = Vec::with_capacity(styles.len());
+                for (p, style) in styles{
+                    let mut cell = self.get_cell(&p).cloned().unwrap_or_default();
+                    previous.push((p, mem::replace(&mut cell.style, style)));
+                    self.insert_cell(&p, &cell);
+                }
+                Some(Edit::SetStyles(previous))
+            },
+        }
+    }
+
+    #[must_use]
     pub fn is_col_empty(&self, at:usize) -> bool{
         for i in 0..self.len{
             if self.cell_exist(&Position{x: at, y:i}) && !self.rows[i].cells[at].val.is_empy(){
@@ -159,10 +679,12 @@ impl Document{
         self.rows.push(Row::default());
     }
 
+    #[must_use]
     pub fn cell_exist(&self, p: &Position) -> bool{
         p.y < self.len && p.x < self.rows[p.y].len
     }
 
+    #[must_use]
     pub fn get_cell(&self, p: &Position) -> Option<&Cell>{
         if self.cell_exist(p){
             self.rows[p.y].cells.get(p.x)
@@ -170,4 +692,121 @@ impl Document{
             None
         }
     }
-}
\ No newline at end of file
+}
+
+fn write_rows<'a, I>(filename: &str, backup: bool, rows: I) -> Result<(), std::io::Error> where I: Iterator<Item = &'a Row>{
+    atomic::write(filename, backup, |file| {
+        for row in rows{
+            file.write_all(row.stringify(";").as_bytes())?;
+            file.write_all(b"\n")?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::reference::{parse_cell, parse_range};
+    use crate::style::Style;
+
+    fn document(text: &str) -> Document{
+        let mut document = Document::default();
+        document.replace_rows(text.lines().map(|line| Row::parse(line, ';')).collect());
+        document.define_name("totals", parse_range("B1:B3").unwrap());
+        document.add_rule(Rule::parse(parse_range("A2:B2").unwrap(), "<0 -> bold").unwrap());
+        document
+    }
+
+    fn value(document: &Document, cell: &str) -> String{
+        document.get_cell(&parse_cell(cell).unwrap()).map(|cell| cell.val.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn recovered_rows_keep_the_styles_and_types(){
+        let mut document = document("a;1\nb;2");
+        document.apply(Edit::SetStyles(vec![(Position{x: 1, y: 0}, Style{bold: true, ..Style::default()})]));
+        document.rows[0].cells[1].val = DataType::Int(1);
+
+        document.recover_rows(vec![Row::parse("a;7;x", ';'), Row::parse("b;2", ';'), Row::parse("c", ';')]);
+        assert_eq!(document.len, 3);
+        assert_eq!(document.rows[0].cells[1].val, DataType::Int(7));
+        assert!(document.rows[0].cells[1].style.bold);
+        assert_eq!(value(&document, "C1"), "x");
+        assert_eq!(value(&document, "A3"), "c");
+    }
+
+    #[test]
+    fn inserted_rows_move_names_rules_and_formulas(){
+        let mut document = document("a;1\nb;2\nc;3\n=SUM(B1:B3);=A2");
+        document.apply(Edit::InsertRow(1));
+        assert_eq!(document.names["TOTALS"].to_string(), "B1:B4");
+        assert_eq!(document.rules[0].range.to_string(), "A3:B3");
+        assert_eq!(value(&document, "A5"), "=SUM(B1:B4)");
+        assert_eq!(value(&document, "B5"), "=A3");
+    }
+
+    #[test]
+    fn deleted_rows_move_names_rules_and_formulas_back_when_undone(){
+        let mut document = document("a;1\nb;2\nc;3\n=SUM(B1:B3);=A2");
+        let inverse = document.apply(Edit::DeleteRow(1)).unwrap();
+        assert_eq!(document.names["TOTALS"].to_string(), "B1:B2");
+        assert!(document.rules.is_empty());
+        assert_eq!(value(&document, "A3"), "=SUM(B1:B2)");
+        assert_eq!(value(&document, "B3"), "=#REF!");
+
+        document.apply(inverse);
+        assert_eq!(document.names["TOTALS"].to_string(), "B1:B3");
+        assert_eq!(document.rules[0].range.to_string(), "A2:B2");
+        assert_eq!(value(&document, "A4"), "=SUM(B1:B3)");
+        assert_eq!(value(&document, "B4"), "=A2");
+    }
+
+    #[test]
+    fn deleted_columns_move_formulas_back_when_undone(){
+        let mut document = document("a;1;=B1*2;=A1&C1");
+        let inverse = document.apply(Edit::DeleteColumn(1)).unwrap();
+        assert_eq!(value(&document, "B1"), "=#REF!*2");
+        assert_eq!(value(&document, "C1"), "=A1&B1");
+        assert!(!document.names.contains_key("TOTALS"));
+
+        document.apply(inverse);
+        assert_eq!(value(&document, "C1"), "=B1*2");
+        assert_eq!(value(&document, "D1"), "=A1&C1");
+        assert_eq!(document.names["TOTALS"].to_string(), "B1:B3");
+    }
+
+    #[test]
+    fn edits_leave_the_document_dirty_until_saved(){
+        let filename = std::env::temp_dir().join(format!("rexcel-document-{}.csv", std::process::id())).to_string_lossy().into_owned();
+        let mut document = Document::default();
+        assert!(!document.is_dirty());
+
+        let revision = document.revision();
+        document.insert(&Position{x: 0, y: 0}, 'a');
+        assert!(document.is_dirty());
+        assert_ne!(document.revision(), revision);
+
+        document.file_name = Some(filename.clone());
+        document.save(false).unwrap();
+        assert!(!document.is_dirty());
+        let opened = Document::open(&filename).unwrap();
+        assert!(!opened.is_dirty());
+        assert_ne!(opened.revision(), document.revision());
+        let _ = fs::remove_file(&filename);
+        let _ = fs::remove_file(format!("{filename}.rexcel"));
+
+        // Rows appended by another program are already on the disk
+        let revision = document.revision();
+        document.append(vec![Row::parse("b", ';')], false);
+        assert!(!document.is_dirty());
+        assert_ne!(document.revision(), revision);
+
+        document.set_col_width(0, 12);
+        assert!(document.is_dirty());
+        document.mark_saved();
+        assert!(!document.is_dirty());
+        document.apply(Edit::InsertRow(0)).unwrap();
+        assert!(document.is_dirty());
+    }
+}
@@ -1,16 +1,46 @@
-use crossterm::event::{Event, KeyCode, KeyModifiers, KeyEvent};
+use crossterm::event::{Event, KeyCode, KeyModifiers, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 use crossterm::ErrorKind;
-use crossterm::style::Color;
+use crossterm::style::{Attribute, Color};
 
 use std::cmp::max;
 use std::io::{Write, stdout};
 use std::env;
+use std::mem;
+use std::time::{Duration, Instant};
 
 use crate::Terminal;
 use crate::Document;
 use crate::Cell;
-
-#[derive(Default, Clone, Copy)]
+use crate::Row;
+use crate::{Edit, History};
+use crate::sort::{SortKey, SortOrder};
+use crate::reference::{column_index, column_name};
+use crate::filter::{Condition, Filter};
+use crate::matcher::{MatchOptions, Matcher, Scope};
+use crate::reference::{cell_name, parse_cell, parse_range, split_sheet, Range};
+use crate::text;
+use crate::style::{Align, NumberFormat};
+use crate::conditional::{Formats, Rule};
+use crate::highlight;
+use crate::temporal;
+use crate::cell::DataType;
+use crate::workbook::{Sheet, Workbook};
+use crate::swap;
+use crate::pane::{self, Area, Split};
+use crate::diff::{Diff, Line, Pairing, Side};
+use crate::merge::{self, Merge};
+use crate::follow::{Change, Follower};
+use crate::spreadsheet;
+
+/// Time between two writes of the swap files
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+/// Time between two reads of the followed files
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// File of a pane, whether it has unsaved changes, and the name and revision of each of its sheets
+type PaneState = (Option<String>, bool, Vec<(String, u64)>);
+
+#[derive(Default, PartialEq, Clone, Copy)]
 pub struct Position{
     pub x: usize,
     pub y: usize
@@ -35,15 +65,55 @@ impl Status{
     }
 }
 
+/// Part of the grid under the mouse
+enum GridHit{
+    Sheet(usize),
+    Cell(Position),
+    Column(usize),
+    Row(usize),
+}
+
+/// Query being searched, and the match under the cursor among all matches
+struct SearchState{
+    query: String,
+    current: Option<usize>,
+    total: usize,
+}
+
 pub struct Editor{
     pub terminal: Terminal,
-    pub cursor_position: Position,
     pub cell_position: Position,
     pub document: Document,
+    /// Sheets of the open file, the one being edited lives in the fields above until another one is shown
+    workbook: Workbook,
+    /// Files shown in split panes, the focused one lives in `workbook` until another one is focused
+    panes: Vec<Workbook>,
+    /// Index of the focused pane
+    pane: usize,
+    split: Split,
+    /// Whether the other panes scroll along with the focused one
+    sync_scroll: bool,
+    /// Differences between the first two panes when comparing them, with how their rows are paired
+    diff: Option<(Diff, Pairing)>,
+    /// Revisions of the two documents compared, and whether the first row was a header, when last compared
+    compared: (u64, u64, bool),
+    /// Difference last moved to
+    diff_line: Option<usize>,
+    /// Conflicts left when merging, our file, the merged one and theirs being shown side by side
+    merge: Option<Merge>,
     clipboard: Option<Cell>,
+    selection: Option<Position>,
+    search: Option<SearchState>,
+    history: History,
+    /// Cell last typed into, and the depth of the history once the typing was recorded,
+    /// the next keystrokes in that cell being undone along with it
+    typing: Option<(Position, usize)>,
     offset: Position,
+    frozen: Position,
     header: bool,
     status: Status,
+    /// When the first thing not in the swap files yet happened
+    unswapped_since: Option<Instant>,
     quit: bool
 }
 
@@ -51,159 +121,1305 @@ impl Editor{
     pub fn new() -> Result<Self, ErrorKind>{
         let args: Vec<String> = env::args().collect();
         let header: bool = args.contains(&"--header".to_string());
-        let status: Status = Status::default();
-        let document = if args.len() > 1{
-            let filename = &args[1];
-            if let Ok(doc) = Document::open(&filename){
-                doc
-            }else{
-                Document::default()
+        let split = if args.contains(&"--vertical".to_string()) { Split::Vertical } else { Split::Horizontal };
+        let mut panes: Vec<Workbook> = args.iter().skip(1).filter(|arg| !arg.starts_with("--"))
+            .map(|filename| Workbook::open(filename).unwrap_or_else(|_| Workbook::new(Some(filename.clone()), Document::default())))
+            .collect();
+        if panes.is_empty(){
+            panes.push(Workbook::default());
+        }
+        let backup = args.contains(&"--backup".to_string());
+        let watch = args.contains(&"--watch".to_string());
+        let follow = args.contains(&"--follow".to_string());
+        for workbook in &mut panes{
+            workbook.backup = backup;
+            workbook.watch = watch;
+            if follow{
+                workbook.follow = workbook.file_name.as_deref()
+                    .filter(|filename| !spreadsheet::is_spreadsheet(filename))
+                    .and_then(|filename| Follower::new(filename, workbook.sheets[0].document.len).ok());
+                // Following already reloads what changes
+                workbook.watch &= workbook.follow.is_none();
             }
-        }else{
-            Document::default()
-        };
+        }
+
+        let mut editor = Self::with_panes(panes, split, header, Terminal::new()?);
+        for index in 0..editor.panes.len(){
+            editor.swap_pane(index);
+            if editor.workbook.follow.is_some(){
+                editor.teleport(&Position{x: 0, y: editor.document.len.saturating_sub(1)});
+            }
+            editor.warn_unsavable();
+        }
+        editor.swap_pane(0);
+        Ok(editor)
+    }
 
-        Ok(Self{
-            terminal: Terminal::new()?,
-            cursor_position: Position{x:5,y:3},
+    /// Editor resolving the conflicts left by `merge::merge`, with our file, the merged one and theirs
+    ///
+    /// # Errors
+    /// Will return an error when the terminal size can't be read
+    pub fn for_merge(ours: Workbook, merged: Workbook, theirs: Workbook, merge: Merge) -> Result<Self, ErrorKind>{
+        let header = env::args().any(|arg| arg == "--header");
+        let mut editor = Self::with_panes(vec![ours, merged, theirs], Split::Vertical, header, Terminal::new()?);
+        let count = merge.conflicts.len();
+        editor.merge = Some(merge);
+        editor.focus_pane(merge::MERGED);
+        editor.status = Status::from(format!("{count} conflict(s), F8 / SHIFT + F8 to move between them, ALT + 1 / ALT + 2 to keep ours / theirs"));
+        Ok(editor)
+    }
+
+    /// Whether the merged file was saved with every conflict resolved
+    #[must_use]
+    pub fn merge_done(&self) -> bool{
+        self.merge.as_ref().is_some_and(|merge| merge.saved)
+    }
+
+    /// Editor showing `panes` on `terminal`, the first one focused
+    fn with_panes(mut panes: Vec<Workbook>, split: Split, header: bool, terminal: Terminal) -> Self{
+        let mut workbook = mem::take(&mut panes[0]);
+        let document = mem::take(&mut workbook.sheets[0].document);
+
+        Self{
+            terminal,
             cell_position: Position{x:0,y:0},
             document,
+            workbook,
+            panes,
+            pane: 0,
+            split,
+            sync_scroll: false,
+            diff: None,
+            compared: (0, 0, false),
+            diff_line: None,
+            merge: None,
             offset: Position::default(),
+            frozen: Position::default(),
             clipboard: None,
+            selection: None,
+            search: None,
+            history: History::default(),
+            typing: None,
             header,
-            status,
+            status: Status::default(),
+            unswapped_since: None,
             quit: false,
-        })
+        }
     }
 
     pub fn run(&mut self){
         Terminal::enter();
+        self.offer_recovery();
         while !self.quit {
             if let Err(error) = self.update(){
-                die(&error);
+                self.die(&error);
             }
 
+            // The swap files are written, and the followed files read, while waiting for the next event
+            let following = self.workbook.follow.is_some() || self.panes.iter().any(|workbook| workbook.follow.is_some());
+            loop{
+                match Terminal::poll(if following { FOLLOW_INTERVAL } else { AUTOSAVE_INTERVAL }){
+                    Ok(true) => break,
+                    Ok(false) => {
+                        self.autosave();
+                        if self.watch_files() | self.follow_files(){
+                            if let Err(error) = self.update(){
+                                self.die(&error);
+                            }
+                        }
+                    },
+                    Err(error) => self.die(&error),
+                }
+            }
+
+            let swapped = self.swap_state();
             if let Err(error) = self.process_input() {
-                die(&error);
+                self.die(&error);
+            }
+            if self.swap_state() != swapped{
+                self.unswapped_since.get_or_insert_with(Instant::now);
+            }
+            if self.unswapped_since.is_some_and(|since| since.elapsed() >= AUTOSAVE_INTERVAL){
+                self.autosave();
             }
         }
 
+        self.remove_swaps();
         Terminal::leave();
     }
 
+    /// Sheets of the file shown in the pane `index`, with their names
+    fn pane_sheets(&self, index: usize) -> Vec<(&str, &Document)>{
+        let workbook = if index == self.pane { &self.workbook } else { &self.panes[index] };
+        workbook.sheets.iter().enumerate().map(|(i, sheet)| {
+            let document = if index == self.pane && i == workbook.active { &self.document } else { &sheet.document };
+            (sheet.name.as_str(), document)
+        }).collect()
+    }
+
+    fn pane_file_name(&self, index: usize) -> Option<&String>{
+        if index == self.pane { self.workbook.file_name.as_ref() } else { self.panes[index].file_name.as_ref() }
+    }
+
+    /// Whether the file shown in the pane `index` has unsaved changes
+    fn pane_dirty(&self, index: usize) -> bool{
+        if index == self.pane { self.workbook.is_dirty() || self.document.is_dirty() } else { self.panes[index].is_dirty() }
+    }
+
+    /// What the swap files depend on, pane by pane
+    fn swap_state(&self) -> Vec<PaneState>{
+        (0..self.panes.len()).map(|index| {
+            let sheets = self.pane_sheets(index).into_iter().map(|(name, document)| (name.to_string(), document.revision())).collect();
+            (self.pane_file_name(index).cloned(), self.pane_dirty(index), sheets)
+        }).collect()
+    }
+
+    /// Writes the swap file of every named file with unsaved changes when something happened since the last time,
+    /// the swap file of a saved one being removed
+    fn autosave(&mut self){
+        if self.unswapped_since.take().is_none(){
+            return;
+        }
+        for index in 0..self.panes.len(){
+            let Some(filename) = self.pane_file_name(index) else{
+                continue;
+            };
+            let result = if self.pane_dirty(index) { swap::write(filename, self.pane_sheets(index).into_iter()) } else { swap::remove(filename) };
+            if let Err(e) = result{
+                self.status = Status::from(format!("Autosave failed: {e}"));
+            }
+        }
+    }
+
+    fn remove_swaps(&self){
+        for filename in (0..self.panes.len()).filter_map(|index| self.pane_file_name(index)){
+            let _ = swap::remove(filename);
+        }
+    }
+
+    /// Offers to reload the watched files another program changed, once per change,
+    /// returning whether it was offered
+    fn watch_files(&mut self) -> bool{
+        let (focused, mut offered) = (self.pane, false);
+        for index in 0..self.panes.len(){
+            let workbook = if index == self.pane { &mut self.workbook } else { &mut self.panes[index] };
+            if !workbook.watch || !workbook.changed_on_disk() || workbook.disk_change_dismissed(){
+                continue;
+            }
+            self.swap_pane(index);
+            offered = true;
+            let filename = self.workbook.file_name.clone().unwrap_or_default();
+            let lost = if self.pane_dirty(index) { ", losing your changes" } else { "" };
+            let message = format!("{filename} was changed by another program, reload it{lost}? (y/n): ");
+            if self.prompt(&message, |_,_,_|{}).unwrap_or(None).as_deref() == Some("y"){
+                self.reload();
+            }else{
+                self.workbook.dismiss_disk_change();
+                self.status = Status::from(format!("{filename} kept as edited, saving it will ask again"));
+            }
+        }
+        self.swap_pane(focused);
+        self.refresh_diff();
+        self.refresh_merge();
+        offered
+    }
+
+    /// Adds the lines appended to the followed files, returning whether there were some.
+    /// A pane whose cursor is on the last row keeps showing it, unless it was moved away.
+    fn follow_files(&mut self) -> bool{
+        let (focused, mut appended) = (self.pane, false);
+        for index in 0..self.panes.len(){
+            let workbook = if index == self.pane { &mut self.workbook } else { &mut self.panes[index] };
+            let Some(follower) = workbook.follow.as_mut() else{
+                continue;
+            };
+            let change = match follower.read(){
+                Ok(Some(change)) => change,
+                Ok(None) => continue,
+                Err(e) => {
+                    self.status = Status::from(format!("Error following file: {e}"));
+                    continue;
+                },
+            };
+
+            self.swap_pane(index);
+            appended = true;
+            let at_end = self.cell_position.y.saturating_add(1) >= self.document.len;
+            match change{
+                Change::Appended{rows, replace_last} => {
+                    // The file is the first sheet
+                    let document = if self.workbook.active == 0 { &mut self.document } else { &mut self.workbook.sheets[0].document };
+                    document.append(rows, replace_last);
+                },
+                Change::Truncated => {
+                    self.reload();
+                    let filename = self.workbook.file_name.clone().unwrap_or_default();
+                    let rows = if self.workbook.active == 0 { self.document.len } else { self.workbook.sheets[0].document.len };
+                    self.workbook.follow = Follower::new(&filename, rows).ok();
+                    self.status = Status::from(format!("{filename} was truncated, read again"));
+                },
+            }
+            if at_end && self.workbook.active == 0{
+                let to = Position{x: self.cell_position.x, y: self.document.len.saturating_sub(1)};
+                self.teleport(&to);
+            }
+        }
+        self.swap_pane(focused);
+        appended
+    }
+
+    /// Reads the file of the focused pane again, its unsaved changes being lost
+    fn reload(&mut self){
+        let Some(filename) = self.workbook.file_name.clone() else{
+            return;
+        };
+        let mut workbook = match Workbook::open(&filename){
+            Ok(workbook) => workbook,
+            Err(e) => {
+                self.status = Status::from(format!("Error reloading file: {e}"));
+                return;
+            },
+        };
+        workbook.backup = self.workbook.backup;
+        workbook.watch = self.workbook.watch;
+        workbook.active = self.workbook.active.min(workbook.sheets.len() - 1);
+
+        let (cursor, offset, frozen) = (self.cell_position, self.offset, self.frozen);
+        self.workbook = workbook;
+        self.load_sheet();
+        (self.cell_position, self.offset, self.frozen) = (cursor, offset, frozen);
+        self.selection = None;
+        self.search = None;
+        self.unswapped_since = Some(Instant::now());
+        self.status = Status::from(format!("{filename} reloaded"));
+    }
+
+    /// Offers to recover the files whose swap file was left by a session that didn't quit normally
+    fn offer_recovery(&mut self){
+        let focused = self.pane;
+        for index in 0..self.panes.len(){
+            self.swap_pane(index);
+            let Some(filename) = self.workbook.file_name.clone().filter(|filename| swap::exists(filename)) else{
+                continue;
+            };
+            let sheets = match swap::read(&filename){
+                Ok(sheets) => sheets,
+                Err(e) => {
+                    self.status = Status::from(format!("Swap file of {filename} not recovered: {e}"));
+                    continue;
+                },
+            };
+
+            // Nothing to recover when the file holds the same values
+            let saved = self.pane_sheets(index);
+            let same = sheets.len() == saved.len() && sheets.iter().zip(&saved).all(|((name, rows), (saved_name, document))| {
+                name == saved_name && rows.len() == document.rows.len()
+                    && rows.iter().zip(&document.rows).all(|(row, saved_row)| row.stringify(";") == saved_row.stringify(";"))
+            });
+            let message = format!("{filename} has unsaved changes from a session that didn't quit, recover them? (y/n): ");
+            if !same && self.prompt(&message, |_,_,_|{}).unwrap_or(None).as_deref() == Some("y"){
+                self.recover(sheets);
+                self.status = Status::from(format!("{filename} recovered, save it to keep the changes"));
+            }else{
+                let _ = swap::remove(&filename);
+            }
+        }
+        self.swap_pane(focused);
+    }
+
+    /// Replaces the values of the sheets of the file being edited by recovered ones
+    fn recover(&mut self, sheets: Vec<(String, Vec<Row>)>){
+        self.store_sheet();
+        for (name, rows) in sheets{
+            let index = self.workbook.index_of(&name).unwrap_or_else(|| {
+                self.workbook.sheets.push(Sheet::new(&name, Document::default()));
+                self.workbook.sheets.len() - 1
+            });
+            self.workbook.sheets[index].document.recover_rows(rows);
+        }
+        self.load_sheet();
+        self.unswapped_since = Some(Instant::now());
+    }
+
+    /// Writes the swap files then panics, the terminal being restored before the message is printed
+    fn die(&mut self, e: &std::io::Error) -> !{
+        self.unswapped_since = Some(Instant::now());
+        self.autosave();
+        panic!("{}, unsaved changes were kept in swap files", e);
+    }
+
     fn process_input(&mut self) -> Result<(), ErrorKind>{
-        let curr_cell = self.cell_position;
         match Terminal::read_event()?{
             Event::Resize(width, height) => {
                 self.terminal.update_size(width as usize, height as usize)?;
             },
-            Event::Key(KeyEvent{code: KeyCode::Down, modifiers: _}) => {
-                self.move_cursor(KeyCode::Down);
+            Event::Key(KeyEvent{code, modifiers}) if self.workbook.follow.is_some() && !Self::reads_only(code, modifiers) => {
+                self.status = Status::from("The file is read-only while it is followed".to_string());
             },
-            Event::Key(KeyEvent{code: KeyCode::Up, modifiers: _})  => {
-                self.move_cursor(KeyCode::Up);
+            Event::Key(KeyEvent{code, modifiers: KeyModifiers::ALT}) => self.process_alt_key(code),
+            Event::Key(KeyEvent{code, modifiers}) => self.process_key(code, modifiers),
+            Event::Mouse(event) => self.process_mouse(event),
+        }
+
+        if self.sync_scroll{
+            self.sync_panes();
+        }
+        self.refresh_diff();
+        self.refresh_merge();
+        Ok(())
+    }
+
+    /// Whether the key leaves the document alone: moving, searching, copying, filtering, changing the view
+    fn reads_only(code: KeyCode, modifiers: KeyModifiers) -> bool{
+        if modifiers == KeyModifiers::ALT{
+            return matches!(code, KeyCode::Char('f' | 'u' | 'e' | 'p' | 'l' | 'm' | 'v'));
+        }
+        match (code, modifiers){
+            // Moving the sheet changes the workbook
+            (KeyCode::PageUp | KeyCode::PageDown, m) => m != KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+            (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right | KeyCode::Home | KeyCode::End | KeyCode::Esc | KeyCode::F(_), _)
+            | (KeyCode::Char('g' | 'o' | 'q' | 'f' | 'c'), KeyModifiers::CONTROL) => true,
+            _ => false,
+        }
+    }
+
+    fn process_alt_key(&mut self, code: KeyCode){
+        let curr_cell = self.cell_position;
+        match code{
+            KeyCode::Up => self.edit(Edit::InsertRow(curr_cell.y), "Row inserted above"),
+            KeyCode::Down => self.edit(Edit::InsertRow(curr_cell.y.saturating_add(1)), "Row inserted below"),
+            KeyCode::Left => self.edit(Edit::InsertColumn(curr_cell.x), "Column inserted left"),
+            KeyCode::Right => self.edit(Edit::InsertColumn(curr_cell.x.saturating_add(1)), "Column inserted right"),
+            KeyCode::Char('r') => self.edit(Edit::DeleteRow(curr_cell.y), "Row deleted"),
+            KeyCode::Char('c') => self.edit(Edit::DeleteColumn(curr_cell.x), "Column deleted"),
+            KeyCode::Char('a') => self.sort(&[SortKey::new(curr_cell.x, SortOrder::Ascending)]),
+            KeyCode::Char('d') => self.sort(&[SortKey::new(curr_cell.x, SortOrder::Descending)]),
+            KeyCode::Char('s') => self.sort_by(),
+            KeyCode::Char('f') => self.filter(),
+            KeyCode::Char('u') => self.clear_filters(),
+            KeyCode::Char('e') => self.export_visible(),
+            KeyCode::Char('=' | '+') => self.resize_column(1),
+            KeyCode::Char('-') => self.resize_column(-1),
+            KeyCode::Char('w') => {
+                self.document.auto_fit(curr_cell.x);
+                self.scroll();
+                self.status = Status::from(format!("Column {} fitted to its content", column_name(curr_cell.x)));
             },
-            Event::Key(KeyEvent{code: KeyCode::Right, modifiers: _})  => {
-                self.move_cursor(KeyCode::Right);
+            KeyCode::Char('p') => self.toggle_freeze(),
+            KeyCode::Char('n') => self.define_name(),
+            KeyCode::Char('b') => {
+                let bold = self.document.get_cell(&curr_cell).is_some_and(|c| c.style.bold);
+                self.style_cells(if bold { "nobold" } else { "bold" });
             },
-            Event::Key(KeyEvent{code: KeyCode::Left, modifiers: _})  => {
-                self.move_cursor(KeyCode::Left);
+            KeyCode::Char('i') => {
+                let italic = self.document.get_cell(&curr_cell).is_some_and(|c| c.style.italic);
+                self.style_cells(if italic { "noitalic" } else { "italic" });
             },
-            Event::Key(KeyEvent{code: KeyCode::Char('q'), modifiers: KeyModifiers::CONTROL}) => {
-                self.quit();
+            KeyCode::Char('k') => self.conditional_format(),
+            KeyCode::Char('o') => self.column_format(),
+            KeyCode::Char('t') => self.add_date_pattern(),
+            KeyCode::Char('h') => self.add_sheet(),
+            KeyCode::Char('j') => self.rename_sheet(),
+            KeyCode::Char('x') => self.delete_sheet(),
+            KeyCode::Char('q') => self.close_pane(),
+            KeyCode::Char('l') => {
+                self.split = if self.split == Split::Horizontal { Split::Vertical } else { Split::Horizontal };
+                self.scroll();
             },
-            Event::Key(KeyEvent{code: KeyCode::Char('f'), modifiers: KeyModifiers::CONTROL}) => self.search(),
-            Event::Key(KeyEvent{code: KeyCode::Char('w'), modifiers: KeyModifiers::CONTROL}) => {
-                self.save();
-                self.quit();
+            KeyCode::Char('v') => self.compare(),
+            KeyCode::Char('1') => self.resolve(merge::OURS),
+            KeyCode::Char('2') => self.resolve(merge::THEIRS),
+            KeyCode::Char('m') => {
+                self.sync_scroll = !self.sync_scroll;
+                self.status = Status::from(format!("Synchronized scroll {}", if self.sync_scroll { "on" } else { "off" }));
             },
-            Event::Key(KeyEvent{code: KeyCode::Char('x'), modifiers: KeyModifiers::CONTROL}) => self.cut(&curr_cell),
-            Event::Key(KeyEvent{code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL}) => self.copy(&curr_cell),
-            Event::Key(KeyEvent{code: KeyCode::Char('v'), modifiers: KeyModifiers::CONTROL}) => self.paste(&curr_cell),
-            Event::Key(KeyEvent{code: KeyCode::Backspace, modifiers: _}) => {
-                self.document.delete(&self.cell_position);
+            KeyCode::Char('y') => {
+                let message = "Style (bold, italic, underline, fg=red, bg=#ffcc00, align=right, fmt=0.00, reset): ";
+                if let Some(spec) = self.prompt(message, |_,_,_|{}).unwrap_or(None){
+                    self.style_cells(&spec);
+                }else{
+                    self.status = Status::from("Canceled.".to_string());
+                }
+            },
+            _ => ()
+        }
+    }
+
+    fn process_key(&mut self, code: KeyCode, modifiers: KeyModifiers){
+        let curr_cell = self.cell_position;
+        match (code, modifiers){
+            (KeyCode::Char('g'), KeyModifiers::CONTROL) => self.go_to(),
+            (KeyCode::Char('o'), KeyModifiers::CONTROL) => self.open_pane(),
+            (KeyCode::F(6), _) => self.focus_pane((self.pane + 1) % self.panes.len()),
+            (KeyCode::F(8), KeyModifiers::SHIFT) if self.merge.is_some() => self.next_conflict(false),
+            (KeyCode::F(8), _) if self.merge.is_some() => self.next_conflict(true),
+            (KeyCode::F(8), KeyModifiers::SHIFT) => self.next_difference(false),
+            (KeyCode::F(8), _) => self.next_difference(true),
+            (KeyCode::PageUp, KeyModifiers::CONTROL) => self.show_sheet(self.workbook.active.checked_sub(1).unwrap_or(self.workbook.sheets.len() - 1)),
+            (KeyCode::PageDown, KeyModifiers::CONTROL) => self.show_sheet((self.workbook.active + 1) % self.workbook.sheets.len()),
+            (KeyCode::PageUp | KeyCode::PageDown, m) if m == KeyModifiers::CONTROL | KeyModifiers::SHIFT => {
+                self.workbook.move_active(code == KeyCode::PageDown);
+            },
+            (KeyCode::Home, KeyModifiers::CONTROL) => self.teleport(&Position::default()),
+            (KeyCode::End, KeyModifiers::CONTROL) => {
+                let to = Position{x: self.document.col_count().saturating_sub(1), y: self.document.len.saturating_sub(1)};
+                self.teleport(&to);
+            },
+            (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right, KeyModifiers::CONTROL) => self.jump(code),
+            (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right, KeyModifiers::SHIFT) => self.extend_selection(code),
+            (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right | KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End, _) => {
+                self.move_cursor(code);
+            },
+            (KeyCode::Char('z'), KeyModifiers::CONTROL) => self.undo(),
+            (KeyCode::Char('y'), KeyModifiers::CONTROL) => self.redo(),
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => self.replace(),
+            (KeyCode::Char('q'), KeyModifiers::CONTROL) => self.quit(),
+            (KeyCode::Char('f'), KeyModifiers::CONTROL) => self.search(),
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                // Ours and theirs are only read when merging
+                let saved = if self.merge.is_some() { merge::MERGED..merge::MERGED + 1 } else { 0..self.panes.len() };
+                for i in saved{
+                    self.focus_pane(i);
+                    self.save();
+                }
+                self.quit();
             },
-            Event::Key(KeyEvent{code: KeyCode::Char(c), modifiers}) => {
+            (KeyCode::Char('x'), KeyModifiers::CONTROL) => self.cut(&curr_cell),
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => self.copy(&curr_cell),
+            (KeyCode::Char('v'), KeyModifiers::CONTROL) => self.paste(&curr_cell),
+            (KeyCode::Backspace, _) => self.change_cell(curr_cell, true, Document::delete),
+            (KeyCode::Char(c), modifiers) => {
                 if c == 's' && modifiers.contains(KeyModifiers::CONTROL){
                     if modifiers.contains(KeyModifiers::ALT){
                         self.save_as();
                     }else{
                         self.save();
                     }
-                    return Ok(());
+                    return;
                 }
-                self.document.insert(&self.cell_position, c);
+                self.change_cell(curr_cell, true, |document, p| document.insert(p, c));
             },
-            Event::Key(KeyEvent{code: KeyCode::Delete, modifiers: _}) => {
-                if self.document.cell_exist(&self.cell_position){
-                    self.document.rows[self.cell_position.y].cells[self.cell_position.x].reset()
+            (KeyCode::Delete, _) => self.change_cell(curr_cell, false, Document::clear_cell),
+            _ => ()
+        }
+    }
+
+    fn process_mouse(&mut self, event: MouseEvent){
+        let (column, line) = (event.column as usize, event.row as usize);
+        if let Some(i) = (0..self.panes.len()).find(|i| self.pane_area(*i).contains(column, line)){
+            self.focus_pane(i);
+        }
+
+        let hit = self.hit_test(event.column as usize, event.row as usize);
+        match (event.kind, hit){
+            (MouseEventKind::Down(MouseButton::Left), Some(GridHit::Cell(p))) => {
+                if event.modifiers.contains(KeyModifiers::SHIFT){
+                    let anchor = self.selection.unwrap_or(self.cell_position);
+                    self.teleport(&p);
+                    self.selection = Some(anchor);
+                }else{
+                    self.teleport(&p);
                 }
             },
+            (MouseEventKind::Drag(MouseButton::Left), Some(GridHit::Cell(p))) => {
+                let anchor = self.selection.unwrap_or(self.cell_position);
+                self.teleport(&p);
+                self.selection = Some(anchor);
+            },
+            (MouseEventKind::Down(MouseButton::Left), Some(GridHit::Column(x))) => {
+                let last = self.document.len.saturating_sub(1);
+                self.teleport(&Position{x, y: 0});
+                self.selection = Some(Position{x, y: last});
+            },
+            (MouseEventKind::Down(MouseButton::Left), Some(GridHit::Row(y))) => {
+                let last = self.document.col_count().saturating_sub(1);
+                self.teleport(&Position{x: 0, y});
+                self.selection = Some(Position{x: last, y});
+            },
+            (MouseEventKind::Down(MouseButton::Left), Some(GridHit::Sheet(i))) => self.show_sheet(i),
+            (MouseEventKind::ScrollDown, _) => self.scroll_view(3, event.modifiers.contains(KeyModifiers::SHIFT)),
+            (MouseEventKind::ScrollUp, _) => self.scroll_view(-3, event.modifiers.contains(KeyModifiers::SHIFT)),
             _ => ()
         }
+    }
+
+    /// What is drawn at a given place of the screen
+    fn hit_test(&self, column: usize, row: usize) -> Option<GridHit>{
+        const MARGIN: usize = 6;
+
+        let area = self.area();
+        if row == area.top{
+            return self.tab_spans().into_iter().position(|(start, end)| (start..end).contains(&column)).map(GridHit::Sheet);
+        }
+        // Sheet tabs and column headers come before the rows
+        let grid_top = area.top + pane::HEADER_LINES;
+        let column = column.checked_sub(area.left)?;
+
+        let x = if column < MARGIN{
+            None
+        }else{
+            let mut left = MARGIN;
+            self.grid_columns().into_iter().find(|x| {
+                left += self.document.col_width(*x);
+                column < left
+            })
+        };
+        let y = row.checked_sub(grid_top).and_then(|i| self.grid_rows().get(i).copied());
+
+        match (x, y){
+            (Some(x), Some(y)) => Some(GridHit::Cell(Position{x, y})),
+            (Some(x), None) if row == grid_top - 1 => Some(GridHit::Column(x)),
+            (None, Some(y)) if column < MARGIN => Some(GridHit::Row(y)),
+            _ => None,
+        }
+    }
+
+    /// Scrolls the grid without moving the cursor, by visible rows or by columns
+    fn scroll_view(&mut self, delta: isize, horizontal: bool){
+        if horizontal{
+            self.offset.x = max(self.offset.x.saturating_add_signed(delta.signum()), self.frozen.x);
+            return;
+        }
+
+        for _ in 0..delta.unsigned_abs(){
+            let y = if delta > 0{
+                Some(self.next_visible_row(self.offset.y))
+            }else{
+                self.prev_visible_row(self.offset.y)
+            };
+            if let Some(y) = y{
+                self.offset.y = max(y, self.frozen.y);
+            }
+        }
+    }
+
+    fn save(&mut self){
+        if self.workbook.file_name.is_none(){
+            let new_name = self.prompt("Save as: ", |_,_,_|{}).unwrap_or(None);
+            let Some(new_name) = new_name else{
+                self.status = Status::from("Canceled.".to_string());
+                return;
+            };
+
+            self.rename_workbook(new_name);
+        }
+
+        if self.workbook.changed_on_disk(){
+            let filename = self.workbook.file_name.clone().unwrap_or_default();
+            let message = format!("{filename} was changed by another program: (r)eload it, (o)verwrite it or (s)ave elsewhere? ");
+            match self.prompt(&message, |_,_,_|{}).unwrap_or(None).as_deref(){
+                Some("o") => (),
+                Some("r") => {
+                    self.reload();
+                    return;
+                },
+                Some("s") => {
+                    self.save_as();
+                    return;
+                },
+                _ => {
+                    self.status = Status::from("Canceled.".to_string());
+                    return;
+                },
+            }
+        }
+
+        if self.workbook.overwrites_spreadsheet(){
+            let filename = self.workbook.file_name.clone().unwrap_or_default();
+            let message = format!("Overwrite {filename}, losing its formulas, formats and charts? (y/n): ");
+            if self.prompt(&message, |_,_,_|{}).unwrap_or(None).as_deref() != Some("y"){
+                self.status = Status::from("Canceled.".to_string());
+                return;
+            }
+        }
+
+        let merged = self.pane == merge::MERGED;
+        let unresolved = self.merge.as_ref().filter(|_| merged).map_or(0, Merge::unresolved);
+        if unresolved > 0{
+            let message = format!("{unresolved} conflict(s) left, save anyway? (y/n): ");
+            if self.prompt(&message, |_,_,_|{}).unwrap_or(None).as_deref() != Some("y"){
+                self.status = Status::from("Canceled.".to_string());
+                return;
+            }
+        }
+
+        self.store_sheet();
+        let saved = self.workbook.save();
+        self.load_sheet();
+        match saved{
+            Ok(()) => {
+                if let Some(merge) = self.merge.as_mut().filter(|_| merged){
+                    merge.saved = unresolved == 0;
+                }
+                self.status = Status::from("File saved successfully.".to_string());
+            },
+            Err(e) => self.status = Status::from(format!("Error saving file: {e}")),
+        }
+    }
+
+    fn cut(&mut self, p: &Position){
+        if self.document.cell_exist(p){
+            self.clipboard = Some(self.document.rows[p.y].cells[p.x].clone());
+            self.change_cell(*p, false, Document::clear_cell);
+
+            self.status = Status::from("Cell Cut".to_string());
+
+        }
+    }
+
+    fn copy(&mut self, p: &Position){
+        if self.document.cell_exist(p){
+            let new_cell = self.document.rows[p.y].cells[p.x].clone();
+            self.clipboard = Some(new_cell);
+
+            self.status = Status::from("Cell Copied".to_string());
+        }
+    }
+
+    fn paste(&mut self, p: &Position){
+        if let Some(cell) = self.clipboard.clone(){
+            self.edit(Edit::SetCells(vec![(*p, cell)]), "Cell Pasted");
+        }
+    }
+
+    fn edit(&mut self, edit: Edit, message: &str){
+        let focus = edit.focus(self.cell_position);
+        if let Some(inverse) = self.document.apply(edit){
+            self.shift_conflicts(Self::replaced_rows(&inverse), false);
+            self.record(inverse);
+            self.cell_position = focus;
+            self.scroll();
+            self.status = Status::from(message.to_string());
+        }
+    }
+
+    /// Records the edit `inverse` undoing the last change
+    fn record(&mut self, inverse: Edit){
+        let merged = self.pane == merge::MERGED;
+        if let Some(merge) = self.merge.as_mut().filter(|_| merged){
+            merge.edited(self.history.depth());
+        }
+        self.history.record(inverse);
+    }
+
+    /// Changes the cell `p` in place and records its previous content, the keystrokes `typed`
+    /// into one cell being undone together until something else is recorded
+    fn change_cell(&mut self, p: Position, typed: bool, change: impl FnOnce(&mut Document, &Position)){
+        let previous = self.document.get_cell(&p).cloned().unwrap_or_default();
+        change(&mut self.document, &p);
+        if typed && self.typing == Some((p, self.history.depth())){
+            return;
+        }
+        self.record(Edit::SetCells(vec![(p, previous)]));
+        self.typing = Some((p, self.history.depth())).filter(|_| typed);
+    }
+
+    fn sort(&mut self, keys: &[SortKey]){
+        let fixed = usize::from(self.header);
+        let order = self.document.sort_order(keys, fixed);
+        let columns = keys.iter().map(|k| column_name(k.column)).collect::<Vec<String>>().join(", ");
+        self.edit(Edit::Reorder(order), &format!("Rows sorted by {columns}"));
+    }
+
+    fn sort_by(&mut self){
+        let spec = self.prompt("Sort by (e.g. B desc, A natural): ", |_,_,_|{}).unwrap_or(None);
+        if let Some(spec) = spec{
+            match SortKey::parse_spec(&spec){
+                Ok(keys) => self.sort(&keys),
+                Err(part) => self.status = Status::from(format!("Invalid sort key: {part}")),
+            }
+        }else{
+            self.status = Status::from("Canceled.".to_string());
+        }
+    }
+
+    fn filter(&mut self){
+        let column = self.cell_position.x;
+        let message = format!("Filter {} (=x, !=x, <n, >n, /regex/, blank, !blank, empty to clear): ", column_name(column));
+        match self.prompt_input(&message, |_,_,_|{}).unwrap_or(None){
+            None => self.status = Status::from("Canceled.".to_string()),
+            Some(expr) if expr.is_empty() => {
+                self.document.filters.remove(column);
+                self.status = Status::from(format!("Filter removed from {}", column_name(column)));
+            },
+            Some(expr) => match Condition::parse(&expr){
+                Ok(condition) => {
+                    self.document.filters.set(Filter{column, condition});
+                    self.status = Status::from(format!("Filter set on {}", column_name(column)));
+                },
+                Err(e) => self.status = Status::from(e),
+            },
+        }
+
+        if !self.row_visible(self.cell_position.y){
+            self.cell_position.y = self.next_visible_row(self.cell_position.y);
+        }
+        self.scroll();
+    }
+
+    fn clear_filters(&mut self){
+        self.document.filters.clear();
+        self.scroll();
+        self.status = Status::from("Filters cleared".to_string());
+    }
+
+    fn export_visible(&mut self){
+        let file_name = self.prompt("Export visible rows to: ", |_,_,_|{}).unwrap_or(None);
+        if let Some(file_name) = file_name{
+            if self.document.export_visible(&file_name, usize::from(self.header)).is_ok(){
+                self.status = Status::from(format!("Visible rows exported to {file_name}"));
+            }else{
+                self.status = Status::from("Error exporting file.".to_string());
+            }
+        }else{
+            self.status = Status::from("Canceled.".to_string());
+        }
+    }
+
+    fn resize_column(&mut self, delta: isize){
+        let x = self.cell_position.x;
+        let width = self.document.col_width(x).saturating_add_signed(delta);
+        self.document.set_col_width(x, width);
+        self.scroll();
+        self.status = Status::from(format!("Column {} width: {}", column_name(x), self.document.col_width(x)));
+    }
+
+    /// Applies a style spec to the selected cells, or to the current cell
+    fn style_cells(&mut self, spec: &str){
+        let cursor = self.cell_position;
+        let range = self.selected_range().unwrap_or_else(|| Range::new(cursor, cursor));
+
+        let mut changes = Vec::new();
+        for y in (range.start.y..=range.end.y).filter(|y| self.row_visible(*y)){
+            for x in range.start.x..=range.end.x{
+                let p = Position{x, y};
+                let mut style = self.document.get_cell(&p).map(|cell| cell.style.clone()).unwrap_or_default();
+                if let Err(word) = style.apply(spec){
+                    self.status = Status::from(format!("Unknown style: {word}"));
+                    return;
+                }
+                changes.push((p, style));
+            }
+        }
+
+        self.edit(Edit::SetStyles(changes), "Style applied");
+        self.cell_position = cursor;
+        self.scroll();
+    }
+
+    fn add_date_pattern(&mut self){
+        if let Some(pattern) = self.prompt("Date pattern (e.g. %d/%m/%Y, %d/%m/%Y %H:%M): ", |_,_,_|{}).unwrap_or(None){
+            self.document.add_pattern(pattern.clone());
+            self.status = Status::from(format!("Dates read with {pattern}"));
+        }else{
+            self.status = Status::from("Canceled.".to_string());
+        }
+    }
+
+    fn column_format(&mut self){
+        let x = self.cell_position.x;
+        let message = format!("Number format of {} (0.00, #,##0, 0.0%, 0.00E+0, $#,##0.00, #.##0,00€, none): ", column_name(x));
+        match self.prompt(&message, |_,_,_|{}).unwrap_or(None){
+            Some(spec) if spec.trim() == "none" => {
+                self.document.set_col_format(x, None);
+                self.status = Status::from(format!("Column {} format removed", column_name(x)));
+            },
+            Some(spec) => match NumberFormat::parse(spec.trim()){
+                Some(format) => {
+                    self.document.set_col_format(x, Some(format));
+                    self.status = Status::from(format!("Column {} formatted as {}", column_name(x), format.spec()));
+                },
+                None => self.status = Status::from(format!("Invalid format: {spec}")),
+            },
+            None => self.status = Status::from("Canceled.".to_string()),
+        }
+    }
+
+    /// Attaches a conditional format to the selected cells, `none` removing the rules covering the cursor
+    fn conditional_format(&mut self){
+        let range = self.selected_range().unwrap_or_else(|| Range::new(self.cell_position, self.cell_position));
+        let message = format!("Rule for {range} (<0 -> fg=red, top 10% -> bold, duplicates -> bg=yellow, scale red green, none): ");
+        match self.prompt(&message, |_,_,_|{}).unwrap_or(None){
+            Some(spec) if spec.trim() == "none" => {
+                let p = self.cell_position;
+                let removed = self.document.remove_rules_at(&p);
+                self.status = Status::from(format!("{removed} rule(s) removed"));
+            },
+            Some(spec) => match Rule::parse(range, &spec){
+                Ok(rule) => {
+                    self.document.add_rule(rule);
+                    self.status = Status::from(format!("Rule added to {range}"));
+                },
+                Err(e) => self.status = Status::from(e),
+            },
+            None => self.status = Status::from("Canceled.".to_string()),
+        }
+    }
+
+    fn row_visible(&self, y: usize) -> bool{
+        (self.header && y == 0) || self.document.row_visible(y)
+    }
+
+    /// First visible row after `y`
+    fn next_visible_row(&self, y: usize) -> usize{
+        let mut next = y.saturating_add(1);
+        while !self.row_visible(next){
+            next = next.saturating_add(1);
+        }
+        next
+    }
+
+    /// Last visible row before `y`
+    fn prev_visible_row(&self, y: usize) -> Option<usize>{
+        (0..y).rev().find(|prev| self.row_visible(*prev))
+    }
+
+    fn undo(&mut self){
+        if let Some(edit) = self.history.undo(&mut self.document){
+            let (focus, moved) = (edit.focus(self.cell_position), Self::replaced_rows(edit));
+            self.shift_conflicts(moved, true);
+            self.cell_position = focus;
+            self.scroll();
+            self.status = Status::from("Undo".to_string());
+        }else{
+            self.status = Status::from("Nothing to undo".to_string());
+        }
+    }
+
+    fn redo(&mut self){
+        if let Some(edit) = self.history.redo(&mut self.document){
+            let (focus, moved) = (edit.focus(self.cell_position), Self::replaced_rows(edit));
+            self.shift_conflicts(moved, true);
+            self.cell_position = focus;
+            self.scroll();
+            self.status = Status::from("Redo".to_string());
+        }else{
+            self.status = Status::from("Nothing to redo".to_string());
+        }
+    }
+
+    fn save_as(&mut self){
+        let new_name = self.prompt("Save as: ", |_,_,_|{}).unwrap_or(None);
+        let Some(new_name) = new_name else{
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        };
+
+        self.rename_workbook(new_name);
+        self.save();
+    }
+
+    fn rename_workbook(&mut self, file_name: String){
+        self.store_sheet();
+        self.workbook.set_file_name(file_name);
+        self.load_sheet();
+    }
+
+    /// Puts the sheet being edited back in the workbook
+    fn store_sheet(&mut self){
+        let sheet = &mut self.workbook.sheets[self.workbook.active];
+        sheet.document = mem::take(&mut self.document);
+        sheet.history = mem::take(&mut self.history);
+        self.typing = None;
+        sheet.cursor = self.cell_position;
+        sheet.offset = self.offset;
+        sheet.frozen = self.frozen;
+    }
+
+    /// Takes the active sheet of the workbook out to edit it
+    fn load_sheet(&mut self){
+        let sheet = &mut self.workbook.sheets[self.workbook.active];
+        self.document = mem::take(&mut sheet.document);
+        self.history = mem::take(&mut sheet.history);
+        self.cell_position = sheet.cursor;
+        self.offset = sheet.offset;
+        self.frozen = sheet.frozen;
+    }
+
+    fn show_sheet(&mut self, index: usize){
+        if index == self.workbook.active || index >= self.workbook.sheets.len(){
+            return;
+        }
+        self.store_sheet();
+        self.workbook.active = index;
+        self.load_sheet();
+        self.selection = None;
+        self.search = None;
+        self.scroll();
+        self.status = Status::from(format!("Sheet {}", self.workbook.sheets[index].name));
+    }
+
+    fn add_sheet(&mut self){
+        let default = self.workbook.next_name();
+        let name = self.prompt_input(&format!("New sheet name (empty for {default}): "), |_,_,_|{}).unwrap_or(None);
+        let Some(name) = name else{
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        };
+
+        let name = if name.is_empty() { default } else { name };
+        match self.workbook.add(&name){
+            Ok(index) => self.show_sheet(index),
+            Err(e) => self.status = Status::from(e),
+        }
+    }
+
+    fn rename_sheet(&mut self){
+        let active = self.workbook.active;
+        let message = format!("Rename sheet {} to: ", self.workbook.sheets[active].name);
+        match self.prompt(&message, |_,_,_|{}).unwrap_or(None){
+            Some(name) => match self.workbook.rename(active, &name){
+                Ok(()) => self.status = Status::from(format!("Sheet renamed {name}")),
+                Err(e) => self.status = Status::from(e),
+            },
+            None => self.status = Status::from("Canceled.".to_string()),
+        }
+    }
+
+    fn delete_sheet(&mut self){
+        let active = self.workbook.active;
+        let name = self.workbook.sheets[active].name.clone();
+        if self.workbook.sheets.len() < 2{
+            self.status = Status::from("The last sheet can't be deleted".to_string());
+            return;
+        }
+        if self.prompt(&format!("Delete sheet {name}? (y/n): "), |_,_,_|{}).unwrap_or(None).as_deref() != Some("y"){
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        }
+
+        self.store_sheet();
+        self.workbook.remove(active);
+        self.load_sheet();
+        self.selection = None;
+        self.search = None;
+        self.scroll();
+        self.status = Status::from(format!("Sheet {name} deleted"));
+    }
+
+    fn area(&self) -> Area{
+        self.pane_area(self.pane)
+    }
+
+    fn pane_area(&self, index: usize) -> Area{
+        Area::of(index, self.panes.len(), self.split, self.terminal.size())
+    }
+
+    /// Puts the file being edited back in its pane
+    fn store_pane(&mut self){
+        self.store_sheet();
+        self.panes[self.pane] = mem::take(&mut self.workbook);
+    }
+
+    /// Takes the file of the focused pane out to edit it
+    fn load_pane(&mut self){
+        self.workbook = mem::take(&mut self.panes[self.pane]);
+        self.load_sheet();
+    }
+
+    /// Edits the file of another pane, leaving the selection and search alone
+    fn swap_pane(&mut self, index: usize){
+        if index == self.pane || index >= self.panes.len(){
+            return;
+        }
+        self.store_pane();
+        self.pane = index;
+        self.load_pane();
+    }
+
+    fn focus_pane(&mut self, index: usize){
+        if index == self.pane || index >= self.panes.len(){
+            return;
+        }
+        self.swap_pane(index);
+        self.selection = None;
+        self.search = None;
+        self.scroll();
+        self.status = Status::from(format!("Editing: {}", self.workbook.file_name.as_deref().unwrap_or("[No Name]")));
+    }
+
+    /// Opens a file in a new pane after the focused one
+    fn open_pane(&mut self){
+        if self.merge.is_some(){
+            self.status = Status::from("No pane can be opened while merging".to_string());
+            return;
+        }
+        let Some(filename) = self.prompt("Open in a new pane: ", |_,_,_|{}).unwrap_or(None) else{
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        };
+
+        let mut workbook = match Workbook::open(&filename){
+            Ok(workbook) => workbook,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Workbook::new(Some(filename), Document::default()),
+            Err(e) => {
+                self.status = Status::from(format!("Error opening file: {e}"));
+                return;
+            },
+        };
+        workbook.backup = self.workbook.backup;
+        workbook.watch = self.workbook.watch;
+        self.panes.insert(self.pane + 1, workbook);
+        self.focus_pane(self.pane + 1);
+        self.warn_unsavable();
+    }
+
+    /// Tells when the file of the focused pane is a spreadsheet that can be read but not saved
+    fn warn_unsavable(&mut self){
+        if let Some(filename) = self.workbook.file_name.as_deref().filter(|f| spreadsheet::is_spreadsheet(f) && !spreadsheet::is_writable(f)){
+            self.status = Status::from(format!("{filename} can't be saved in its format, CTRL + ALT + S saves it as .xlsx or .csv"));
+        }
+    }
+
+    /// Closes the focused pane, changes not saved being lost
+    fn close_pane(&mut self){
+        if self.merge.is_some(){
+            self.status = Status::from("No pane can be closed while merging".to_string());
+            return;
+        }
+        if self.panes.len() < 2{
+            self.status = Status::from("The last pane can't be closed".to_string());
+            return;
+        }
+
+        let name = self.workbook.file_name.clone().unwrap_or_else(|| "[No Name]".to_string());
+        self.store_pane();
+        self.panes.remove(self.pane);
+        self.pane = self.pane.min(self.panes.len() - 1);
+        if self.panes.len() < 2{
+            self.diff = None;
+        }
+        self.load_pane();
+        self.selection = None;
+        self.search = None;
+        self.scroll();
+        self.status = Status::from(format!("{name} closed"));
+    }
+
+    /// Document shown in the pane `index`
+    fn pane_document(&self, index: usize) -> &Document{
+        if index == self.pane{
+            &self.document
+        }else{
+            let workbook = &self.panes[index];
+            &workbook.sheets[workbook.active].document
+        }
+    }
+
+    /// History of the sheet shown in the pane `index`
+    fn pane_history(&self, index: usize) -> &History{
+        if index == self.pane{
+            &self.history
+        }else{
+            let workbook = &self.panes[index];
+            &workbook.sheets[workbook.active].history
+        }
+    }
+
+    /// Side of the comparison shown in the pane being edited
+    fn diff_side(&self) -> Option<Side>{
+        match self.pane{
+            0 => Some(Side::Old),
+            1 => Some(Side::New),
+            _ => None,
+        }
+    }
+
+    /// Compares the first pane to the second one, or stops comparing them
+    fn compare(&mut self){
+        if self.diff.take().is_some(){
+            self.status = Status::from("Comparison ended".to_string());
+            return;
+        }
+        if self.panes.len() < 2{
+            self.status = Status::from("Open a second file to compare with CTRL + O".to_string());
+            return;
+        }
+
+        let message = "Pair rows by key column (empty to pair them by number): ";
+        let Some(key) = self.prompt_input(message, |_,_,_|{}).unwrap_or(None) else{
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        };
+        let pairing = if key.trim().is_empty(){
+            Pairing::Position
+        }else if let Some(x) = column_index(key.trim()){
+            Pairing::Key(x)
+        }else{
+            self.status = Status::from(format!("Invalid column: {key}"));
+            return;
+        };
 
-        Ok(())
+        self.diff_panes(pairing);
+        self.diff_line = None;
+        let summary = self.diff.as_ref().map(|(diff, _)| diff.summary().to_string()).unwrap_or_default();
+        self.status = Status::from(format!("{summary}, F8 / SHIFT + F8 to move between them"));
     }
 
-    fn save(&mut self){
-        if self.document.file_name.is_none(){
-            let new_name = self.prompt("Save as: ", |_,_,_|{}).unwrap_or(None);
-            if new_name.is_none(){
-                self.status = Status::from("Canceled.".to_string());
-                return;
+    fn diff_panes(&mut self, pairing: Pairing){
+        self.diff = Some((Diff::new(self.pane_document(0), self.pane_document(1), pairing, usize::from(self.header)), pairing));
+        self.compared = (self.pane_document(0).revision(), self.pane_document(1).revision(), self.header);
+    }
+
+    /// Compares the panes again when one of them changed
+    fn refresh_diff(&mut self){
+        if let Some((_, pairing)) = self.diff{
+            if (self.pane_document(0).revision(), self.pane_document(1).revision(), self.header) != self.compared{
+                self.diff_panes(pairing);
             }
+        }
+    }
 
-            self.document.file_name = new_name;
+    /// Moves both panes to the next or previous difference
+    fn next_difference(&mut self, forward: bool){
+        let Some((diff, _)) = &self.diff else{
+            self.status = Status::from("Not comparing, ALT + V compares the first two panes".to_string());
+            return;
+        };
+        let count = diff.lines.len();
+        if count == 0{
+            self.status = Status::from("No difference".to_string());
+            return;
         }
 
-        if self.document.save().is_ok(){
-            self.status = Status::from("File saved successfully.".to_string());
-        }else{
-            self.status = Status::from("Error saving file.".to_string());
+        let i = match (self.diff_line, forward){
+            (Some(i), true) => (i + 1) % count,
+            (Some(i), false) => i.checked_sub(1).unwrap_or(count - 1),
+            (None, true) => 0,
+            (None, false) => count - 1,
+        };
+        let line = &diff.lines[i];
+        let rows = [line.old, line.new];
+        let summary = match (line.old, line.new){
+            (Some(o), None) => format!("row {} removed", o + 1),
+            (None, Some(n)) => format!("row {} added", n + 1),
+            _ => format!("row {} changed ({})", line.new.unwrap_or_default() + 1, line.columns.iter().map(|x| column_name(*x)).collect::<Vec<String>>().join(", ")),
+        };
+        let first_column = line.columns.first().copied();
+
+        let focused = self.pane;
+        for (index, row) in rows.iter().copied().enumerate(){
+            if let Some(y) = row{
+                self.swap_pane(index);
+                let to = Position{x: first_column.unwrap_or(self.cell_position.x), y};
+                self.teleport(&to);
+            }
         }
+        self.swap_pane(focused);
+        self.diff_line = Some(i);
+        self.status = Status::from(format!("Difference {} of {count}: {summary}", i + 1));
     }
 
-    fn cut(&mut self, p: &Position){
-        if self.document.cell_exist(p){
-            self.clipboard = Some(self.document.rows[p.y].cells[p.x].clone());
-            self.document.rows[p.y].cells[p.x].reset();
-
-            self.status = Status::from("Cell Cut".to_string());
+    /// Checks which conflicts the edits of the merged file resolved, or unresolved when undone
+    fn refresh_merge(&mut self){
+        let Some(mut merge) = self.merge.take() else{
+            return;
+        };
+        merge.refresh(self.pane_document(merge::MERGED), self.pane_history(merge::MERGED).depth());
+        self.merge = Some(merge);
+    }
 
+    /// Keeps the conflicts on their rows when an edit of the merged file inserted, deleted or replaced
+    /// some, `from_history` telling the edit was undone or redone
+    fn shift_conflicts(&mut self, replaced: Option<(usize, usize, usize)>, from_history: bool){
+        if self.pane != merge::MERGED{
+            return;
+        }
+        if let (Some(merge), Some((at, removed, inserted))) = (self.merge.as_mut(), replaced){
+            merge.rows_replaced(at, removed, inserted, from_history);
         }
     }
 
-    fn copy(&mut self, p: &Position){
-        if self.document.cell_exist(p){
-            let new_cell = self.document.rows[p.y].cells[p.x].clone();
-            self.clipboard = Some(new_cell);
-
-            self.status = Status::from("Cell Copied".to_string());
+    /// First row an edit replaced, the number of rows it removed and the number it inserted,
+    /// told by the edit `inverse` undoing it
+    fn replaced_rows(inverse: &Edit) -> Option<(usize, usize, usize)>{
+        match inverse{
+            Edit::DeleteRow(at) => Some((*at, 0, 1)),
+            Edit::RestoreRow(at, _, _) => Some((*at, 1, 0)),
+            Edit::ReplaceRows(at, count, rows) => Some((*at, rows.len(), *count)),
+            _ => None,
         }
     }
 
-    fn paste(&mut self, p: &Position){
-        if self.clipboard.is_some(){
-            self.document.insert_cell(p, self.clipboard.as_ref().unwrap());
-
-            self.status = Status::from("Cell Pasted".to_string());
+    /// Moves the three panes to the next or previous conflict not resolved yet
+    fn next_conflict(&mut self, forward: bool){
+        let Some(merge) = &self.merge else{
+            return;
+        };
+        let count = merge.conflicts.len();
+        let start = merge.current.unwrap_or(if forward { count - 1 } else { 0 });
+        let steps = (1..=count).map(|step| if forward { (start + step) % count } else { (start + count - step % count) % count });
+        let Some(i) = steps.into_iter().find(|i| !merge.conflicts[*i].resolved) else{
+            self.status = Status::from("Every conflict is resolved, CTRL + S saves the merged file".to_string());
+            return;
+        };
 
+        let conflict = &merge.conflicts[i];
+        let cells = conflict.cells;
+        let rows = |text: &str| text.replace('\n', " | ");
+        let summary = match (conflict.rows, conflict.ours.is_empty(), conflict.theirs.is_empty()){
+            (Some(_), true, _) => format!("rows deleted by ours, changed by theirs to {}", rows(&conflict.theirs)),
+            (Some(_), _, true) => format!("rows changed by ours to {}, deleted by theirs", rows(&conflict.ours)),
+            (Some(_), _, _) => format!("rows changed by ours to {}, by theirs to {}", rows(&conflict.ours), rows(&conflict.theirs)),
+            (None, _, _) => format!("ours: {}, theirs: {}, base: {}", conflict.ours, conflict.theirs, conflict.base),
+        };
+        let focused = self.pane;
+        for (index, p) in cells.iter().enumerate(){
+            if let Some(p) = p{
+                self.swap_pane(index);
+                self.teleport(p);
+            }
+        }
+        self.swap_pane(focused);
+        if let Some(merge) = self.merge.as_mut(){
+            merge.current = Some(i);
         }
+        self.status = Status::from(format!("Conflict {} of {count}: {summary}", i + 1));
     }
 
-    fn save_as(&mut self){
-        let new_name = self.prompt("Save as: ", |_,_,_|{}).unwrap_or(None);
-        if new_name.is_none(){
-            self.status = Status::from("Canceled.".to_string());
+    /// Resolves the conflict under the cursor with the value of our file or theirs
+    fn resolve(&mut self, side: usize){
+        let Some(merge) = &self.merge else{
             return;
-        }
+        };
+        let Some(i) = merge.conflict_at(self.pane, &self.cell_position) else{
+            self.status = Status::from("No conflict under the cursor".to_string());
+            return;
+        };
 
-        self.document.file_name = new_name;
+        let conflict = &merge.conflicts[i];
+        let (cells, rows) = (conflict.cells, conflict.rows);
+        let kept = if side == merge::OURS { conflict.ours == conflict.pending } else { conflict.theirs == conflict.pending };
+        let Some(at) = cells[merge::MERGED] else{
+            return;
+        };
+        let message = if side == merge::OURS { "Kept ours" } else { "Kept theirs" };
+        let edit = match rows{
+            // The rows of the side, none where it deleted them
+            Some(rows) => {
+                let side_rows = cells[side].map(|p| self.pane_document(side).rows.iter().skip(p.y).take(rows[side]).cloned().collect());
+                Edit::ReplaceRows(at.y, rows[merge::MERGED], side_rows.unwrap_or_default())
+            },
+            None => Edit::SetCells(vec![(at, cells[side].and_then(|p| self.pane_document(side).get_cell(&p)).cloned().unwrap_or_default())]),
+        };
 
-        if self.document.save().is_ok(){
-            self.status = Status::from("File saved successfully.".to_string());
-        }else{
-            self.status = Status::from("Error saving file.".to_string());
+        let focused = self.pane;
+        self.swap_pane(merge::MERGED);
+        self.edit(edit, message);
+        self.swap_pane(focused);
+        let depth = self.pane_history(merge::MERGED).depth();
+        if let Some(conflict) = self.merge.as_mut().map(|merge| &mut merge.conflicts[i]){
+            conflict.kept = Some(depth).filter(|_| kept);
+            conflict.resolved = true;
+        }
+        self.status = Status::from(message.to_string());
+    }
+
+    /// Scrolls the other panes to where the focused one is
+    fn sync_panes(&mut self){
+        let (offset, focused) = (self.offset, self.pane);
+        for (_, workbook) in self.panes.iter_mut().enumerate().filter(|(i, _)| *i != focused){
+            workbook.sheets[workbook.active].offset = offset;
         }
     }
 
@@ -211,27 +1427,35 @@ impl Editor{
         Terminal::hide_cursor();
         Terminal::goto(&Position::default());
         self.draw_status_message()?;
-        
+
         Terminal::goto(&Position{x:0, y:1});
         Terminal::clear_line();
         // write!(stdout(), "{}/{}", self.cell_position.x, self.cell_position.y)?;
         // Terminal::clear();
         self.draw_edit_line()?;
-        Terminal::goto(&Position{x:0, y:2});
 
-        self.draw_grid()?;
+        self.draw_panes()?;
 
         // Terminal::goto(&self.cursor_position);
         // Terminal::show_cursor();
         Terminal::flush()
     }
 
+    /// Quits, asking first when a file has unsaved changes
     fn quit(&mut self){
+        let unsaved = (0..self.panes.len()).filter(|index| self.pane_dirty(*index)).count();
+        if unsaved > 0{
+            let message = format!("{unsaved} file(s) with unsaved changes, quit anyway? (y/n): ");
+            if self.prompt(&message, |_,_,_|{}).unwrap_or(None).as_deref() != Some("y"){
+                self.status = Status::from("Canceled.".to_string());
+                return;
+            }
+        }
         self.quit = true;
     }
 
     fn move_cursor(&mut self, key: KeyCode){
-        let size = self .terminal.size();
+        self.selection = None;
         match key{
             KeyCode::Left => {
                 self.cell_position.x = self.cell_position.x.saturating_sub(1);
@@ -240,109 +1464,535 @@ impl Editor{
                 self.cell_position.x = self.cell_position.x.saturating_add(1);
             },
             KeyCode::Up => {
-                if self.cell_position.y.saturating_sub(self.offset.y) == 0 && self.offset.y > 0{
-                    self.offset.y = self.offset.y.saturating_sub(1);
+                if let Some(y) = self.prev_visible_row(self.cell_position.y){
+                    self.cell_position.y = y;
                 }
-                self.cell_position.y = self.cell_position.y.saturating_sub(1);
             },
             KeyCode::Down => {
-                if self.cell_position.y.saturating_sub(self.offset.y) == size.height.saturating_sub(1){
-                    self.offset.y = self.offset.y.saturating_add(1);
+                self.cell_position.y = self.next_visible_row(self.cell_position.y);
+            },
+            KeyCode::PageUp => {
+                for _ in 0..self.area().rows{
+                    if let Some(y) = self.prev_visible_row(self.cell_position.y){
+                        self.cell_position.y = y;
+                    }
+                    if let Some(y) = self.prev_visible_row(self.offset.y){
+                        self.offset.y = y;
+                    }
+                }
+            },
+            KeyCode::PageDown => {
+                for _ in 0..self.area().rows{
+                    self.cell_position.y = self.next_visible_row(self.cell_position.y);
+                    self.offset.y = self.next_visible_row(self.offset.y);
                 }
-                self.cell_position.y = self.cell_position.y.saturating_add(1);
-                
+            },
+            KeyCode::Home => {
+                self.cell_position.x = 0;
+            },
+            KeyCode::End => {
+                let row_len = self.document.rows.get(self.cell_position.y).map_or(0, |row| row.len);
+                self.cell_position.x = row_len.saturating_sub(1);
             },
             _ => ()
         }
+        self.scroll();
+    }
+
+    /// Moves to the edge of the data region in the direction of `key`, like spreadsheets do with CTRL + Arrow
+    fn jump(&mut self, key: KeyCode){
+        let cols = self.document.col_count();
+        let rows = self.document.len;
+        let step = |editor: &Self, p: Position| -> Option<Position>{
+            match key{
+                KeyCode::Left => p.x.checked_sub(1).map(|x| Position{x, y: p.y}),
+                KeyCode::Right if p.x.saturating_add(1) < cols => Some(Position{x: p.x + 1, y: p.y}),
+                KeyCode::Up => editor.prev_visible_row(p.y).map(|y| Position{x: p.x, y}),
+                KeyCode::Down => Some(editor.next_visible_row(p.y)).filter(|y| *y < rows).map(|y| Position{x: p.x, y}),
+                _ => None,
+            }
+        };
+        let filled = |p: &Position| self.document.get_cell(p).is_some_and(|cell| !cell.render(0).is_empty());
+
+        let Some(mut target) = step(self, self.cell_position) else{
+            return;
+        };
+        if filled(&self.cell_position) && filled(&target){
+            // Last filled cell before a blank one
+            while let Some(next) = step(self, target).filter(|next| filled(next)){
+                target = next;
+            }
+        }else{
+            // Next filled cell, or the edge of the data
+            while !filled(&target){
+                match step(self, target){
+                    Some(next) => target = next,
+                    None => break,
+                }
+            }
+        }
+        self.teleport(&target);
+    }
+
+    fn go_to(&mut self){
+        let Some(reference) = self.prompt("Go to (B17, R17C2, row number or name): ", |_,_,_|{}).unwrap_or(None) else{
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        };
+
+        let (sheet, reference) = split_sheet(reference.trim());
+        if let Some(sheet) = sheet{
+            let Some(index) = self.workbook.index_of(sheet) else{
+                self.status = Status::from(format!("Unknown sheet: {sheet}"));
+                return;
+            };
+            self.show_sheet(index);
+        }
+
+        if let Ok(row) = reference.trim().parse::<usize>(){
+            let to = Position{x: self.cell_position.x, y: row.saturating_sub(1)};
+            self.teleport(&to);
+        }else if let Some(to) = parse_cell(reference){
+            self.teleport(&to);
+        }else if let Some(range) = self.document.named_range(reference).or_else(|| parse_range(reference)){
+            self.teleport(&range.start);
+            self.selection = Some(range.end);
+        }else{
+            self.status = Status::from(format!("Unknown reference: {reference}"));
+            return;
+        }
+        self.status = Status::from(format!("Moved to {}", cell_name(&self.cell_position)));
+    }
+
+    fn define_name(&mut self){
+        let range = self.selected_range().unwrap_or_else(|| Range::new(self.cell_position, self.cell_position));
+        let message = format!("Name for {range}: ");
+        match self.prompt(&message, |_,_,_|{}).unwrap_or(None){
+            Some(name) if parse_cell(&name).is_none() && !name.contains(char::is_whitespace) => {
+                self.document.define_name(&name, range);
+                self.status = Status::from(format!("{range} named {name}"));
+            },
+            Some(name) => self.status = Status::from(format!("Invalid name: {name}")),
+            None => self.status = Status::from("Canceled.".to_string()),
+        }
+    }
+
+    fn extend_selection(&mut self, key: KeyCode){
+        let anchor = self.selection.unwrap_or(self.cell_position);
+        self.move_cursor(key);
+        self.selection = Some(anchor);
+    }
+
+    fn selected_range(&self) -> Option<Range>{
+        self.selection.map(|anchor| Range::new(anchor, self.cell_position))
+    }
+
+    /// Sum of the numbers or durations of the selection, or the span of its dates
+    fn selection_summary(&self) -> Option<String>{
+        let range = self.selected_range()?;
+        let values: Vec<&DataType> = (range.start.y..=range.end.y)
+            .filter(|y| self.row_visible(*y))
+            .flat_map(|y| (range.start.x..=range.end.x).map(move |x| Position{x, y}))
+            .filter_map(|p| self.document.get_cell(&p))
+            .map(|cell| &cell.val)
+            .filter(|val| !val.to_string().trim().is_empty())
+            .collect();
+        let (first, rest) = values.split_first()?;
+
+        if values.iter().all(|val| matches!(val, DataType::Duration(_))){
+            let total = rest.iter().try_fold((*first).clone(), |total, val| temporal::add(&total, val))?;
+            Some(format!("Count: {}  Sum: {total}", values.len()))
+        }else if values.iter().all(|val| matches!(val, DataType::Date(..) | DataType::DateTime(..))){
+            let earliest = values.iter().min_by_key(|val| val.as_seconds())?;
+            let latest = values.iter().max_by_key(|val| val.as_seconds())?;
+            Some(format!("{earliest} to {latest}: {}", temporal::sub(latest, earliest)?))
+        }else{
+            let numbers: Vec<f64> = values.iter().filter_map(|val| val.as_number()).collect();
+            if numbers.is_empty(){
+                return None;
+            }
+            let sum = Cell{val: DataType::Float(numbers.iter().sum()), ..Cell::default()};
+            Some(format!("Count: {}  Sum: {}", numbers.len(), sum.render(0)))
+        }
     }
 
     fn teleport(&mut self, to: &Position){
+        self.selection = None;
         self.cell_position = *to;
-        let mut new_offset: Position = Position::default();
-        new_offset.y = to.y.saturating_sub(self.terminal.size().height);
-        self.offset = new_offset;
+        self.scroll();
     }
 
     fn search(&mut self){
-        let old_position = self.cell_position.clone(); 
-        let mut direction = SearchDirection::Forward;
+        let old_position = self.cell_position;
         let query = self.prompt(
-            "Search (ESC to cancel, Arrows to navigate): ", 
+            "Search (ESC to cancel, Arrows to navigate): ",
             |editor, key, query|{
-                let mut moved = false;
-                match key{
-                    KeyCode::Right | KeyCode::Down => {
-                        direction = SearchDirection::Forward;
-                        editor.move_cursor(KeyCode::Right);
-                        moved = true;
+                let Position{x, y} = editor.cell_position;
+                let (from, direction) = match key{
+                    KeyCode::Right | KeyCode::Down => (Position{x: x.saturating_add(1), y}, SearchDirection::Forward),
+                    KeyCode::Left | KeyCode::Up if x == 0 => {
+                        let y = y.checked_sub(1).unwrap_or_else(|| editor.document.len.saturating_sub(1));
+                        (Position{x: usize::MAX, y}, SearchDirection::Backward)
                     },
-                    KeyCode::Left | KeyCode::Up => direction = SearchDirection::Backward,
-                    _ => direction = SearchDirection::Forward,
-                }
-                if let Some(position) = editor.document.find(&query, &editor.cell_position, direction){
+                    KeyCode::Left | KeyCode::Up => (Position{x: x - 1, y}, SearchDirection::Backward),
+                    _ => (editor.cell_position, SearchDirection::Forward),
+                };
+
+                if let Some(position) = editor.document.find(query, &from, direction){
                     editor.cell_position = position;
                     editor.scroll();
-                }else if moved{
-                    editor.move_cursor(KeyCode::Left);
                 }
+
+                let found = editor.document.find_all(query);
+                let current = found.iter().position(|p| p.x == editor.cell_position.x && p.y == editor.cell_position.y);
+                editor.search = if query.is_empty(){
+                    None
+                }else{
+                    Some(SearchState{
+                        query: query.clone(),
+                        current: current.map(|i| i + 1),
+                        total: found.len(),
+                    })
+                };
             }).unwrap_or(None);
 
         if query.is_none(){
-            self.cursor_position = old_position;
+            self.cell_position = old_position;
             self.scroll();
         }
 
+        self.search = None;
         self.status = Status::default();
     }
 
+    fn replace(&mut self){
+        let scope = self.selected_range();
+        let Some(query) = self.prompt("Replace: ", |_,_,_|{}).unwrap_or(None) else{
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        };
+        let Some(replacement) = self.prompt_input("Replace with: ", |_,_,_|{}).unwrap_or(None) else{
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        };
+        let Some(flags) = self.prompt_input("Options (r: regex, c: match case, w: whole cell, k: current column): ", |_,_,_|{}).unwrap_or(None) else{
+            self.status = Status::from("Canceled.".to_string());
+            return;
+        };
+
+        let scope = match scope{
+            Some(range) => Scope::Range(range),
+            None if flags.contains('k') => Scope::Column(self.cell_position.x),
+            None => Scope::All,
+        };
+        let matcher = match Matcher::new(&query, MatchOptions::from_flags(&flags)){
+            Ok(matcher) => matcher,
+            Err(e) => {
+                self.status = Status::from(format!("Invalid regex: {e}"));
+                return;
+            },
+        };
+
+        let mut found = Vec::new();
+        for y in (0..self.document.len).filter(|y| self.row_visible(*y)){
+            for x in 0..self.document.rows[y].len{
+                let p = Position{x, y};
+                let text = self.document.rows[y].cells[x].val.to_string();
+                if scope.contains(&p) && matcher.is_match(&text){
+                    found.push((p, text));
+                }
+            }
+        }
+
+        let old_position = self.cell_position;
+        let total = found.len();
+        let mut replace_all = false;
+        let mut count = 0;
+        let mut changes = Vec::new();
+        for (i, (p, text)) in found.into_iter().enumerate(){
+            if !replace_all{
+                self.cell_position = p;
+                self.scroll();
+                self.status = Status::from(format!("Replace match {} of {total}? (y: yes, n: no, a: all, Esc: stop)", i + 1));
+                match self.confirm_replace(){
+                    Some('y') => (),
+                    Some('a') => replace_all = true,
+                    Some(_) => continue,
+                    None => break,
+                }
+            }
+
+            count += matcher.count(&text);
+            // Only the value changes, the style and the type stay
+            let mut cell = self.document.get_cell(&p).cloned().unwrap_or_default();
+            cell.val = cell.val.with_text(matcher.replace(&text, &replacement));
+            changes.push((p, cell));
+        }
+
+        if changes.is_empty(){
+            self.status = Status::from("No replacement".to_string());
+        }else{
+            self.edit(Edit::SetCells(changes), &format!("{count} replacement(s)"));
+        }
+        self.cell_position = old_position;
+        self.scroll();
+    }
+
+    /// Waits for the answer to the replacement shown, `y`, `n` or `a`, `None` when stopped with Esc.
+    /// The screen is drawn again when the terminal is resized, other keys and the mouse being ignored
+    fn confirm_replace(&mut self) -> Option<char>{
+        self.update().ok()?;
+        loop{
+            match Terminal::read_event().ok()?{
+                Event::Key(KeyEvent{code: KeyCode::Char(answer @ ('y' | 'n' | 'a')), ..}) => return Some(answer),
+                Event::Key(KeyEvent{code: KeyCode::Esc, ..}) => return None,
+                Event::Resize(width, height) => {
+                    self.terminal.update_size(width as usize, height as usize).ok()?;
+                    self.scroll();
+                    self.update().ok()?;
+                },
+                Event::Key(_) | Event::Mouse(_) => (),
+            }
+        }
+    }
+
     fn scroll(&mut self){
         let Position {x,y} = self.cell_position;
-        let size = self.terminal.size();
-        let mut offset = &mut self.offset;
-        if y < offset.y{
-            offset.y = y;
-        }else if y >= offset.y.saturating_add(size.height){
-            offset.y = y.saturating_sub(size.height).saturating_add(1);
+
+        // Frozen rows and columns never scroll
+        if y >= self.frozen.y{
+            let frozen_rows = (0..self.frozen.y).filter(|y| self.row_visible(*y)).count();
+            let height = max(self.area().rows.saturating_sub(frozen_rows), 1);
+            self.offset.y = max(self.offset.y, self.frozen.y);
+
+            // Hidden rows don't take any space on the screen
+            let mut top = y;
+            let mut shown = 1;
+            while shown < height{
+                match self.prev_visible_row(top){
+                    Some(prev) if prev >= self.offset.y => {
+                        top = prev;
+                        shown += 1;
+                    },
+                    _ => break,
+                }
+            }
+
+            if y < self.offset.y{
+                self.offset.y = y;
+            }else if shown >= height{
+                self.offset.y = top;
+            }
         }
 
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(size.width) {
-            offset.x = x.saturating_sub(size.width).saturating_add(1);
+        if x >= self.frozen.x{
+            self.offset.x = max(self.offset.x, self.frozen.x);
+            if x < self.offset.x {
+                self.offset.x = x;
+            }
+            while !self.grid_columns().contains(&x) && self.offset.x < x{
+                self.offset.x = self.offset.x.saturating_add(1);
+            }
         }
     }
 
-    fn draw_edit_line(&mut self) -> Result<(), std::io::Error>{
-        let curr_pos: &Position = &self.cell_position;
-        if self.document.cell_exist(curr_pos){
-            write!(stdout(), "{}", self.document.get_cell(curr_pos).unwrap().val)
+    /// Columns drawn on the screen: the frozen ones, then the ones from `offset.x` that fit
+    fn grid_columns(&self) -> Vec<usize>{
+        let mut available = self.area().width.saturating_sub(6);
+        let mut columns = Vec::new();
+        let scrolled = max(self.offset.x, self.frozen.x);
+        for x in (0..self.frozen.x).chain(scrolled..){
+            let width = self.document.col_width(x);
+            if width > available{
+                break;
+            }
+            available -= width;
+            columns.push(x);
+        }
+
+        if !columns.contains(&scrolled){
+            columns.push(scrolled);
+        }
+        columns
+    }
+
+    /// Rows drawn on the screen: the visible frozen ones, then the visible ones from `offset.y`
+    fn grid_rows(&self) -> Vec<usize>{
+        let height = self.area().rows;
+        let mut rows: Vec<usize> = (0..self.frozen.y).filter(|y| self.row_visible(*y)).take(height).collect();
+
+        let scrolled = max(self.offset.y, self.frozen.y);
+        let mut y = if self.row_visible(scrolled){
+            scrolled
+        }else{
+            self.next_visible_row(scrolled)
+        };
+        while rows.len() < height{
+            rows.push(y);
+            y = self.next_visible_row(y);
+        }
+        rows
+    }
+
+    fn toggle_freeze(&mut self){
+        if self.frozen.x == 0 && self.frozen.y == 0{
+            self.frozen = self.cell_position;
+            self.offset = Position{x: max(self.offset.x, self.frozen.x), y: max(self.offset.y, self.frozen.y)};
+            self.status = Status::from(format!("Frozen {} row(s) and {} column(s)", self.frozen.y, self.frozen.x));
         }else{
-            write!(stdout(), "")
+            self.frozen = Position::default();
+            self.status = Status::from("Panes unfrozen".to_string());
+        }
+        self.scroll();
+    }
+
+    /// File name shown before the sheet tabs when the screen is split
+    fn pane_title(&self) -> Option<String>{
+        (self.panes.len() > 1).then(|| format!("[{}] ", self.workbook.file_name.as_deref().unwrap_or("No Name")))
+    }
+
+    /// Columns taken by the tab of each sheet
+    fn tab_spans(&self) -> Vec<(usize, usize)>{
+        let mut left = self.area().left + self.pane_title().map_or(0, |title| text::width(&title));
+        self.workbook.sheets.iter().map(|sheet| {
+            let start = left;
+            left += text::width(&sheet.name) + 3;
+            (start, left)
+        }).collect()
+    }
+
+    /// Sheet tabs of the pane, the active tab being dimmed unless the pane has the focus
+    fn draw_tabs(&self, focused: bool) -> Result<(), std::io::Error>{
+        let area = self.area();
+        let right = area.left + area.width;
+        let mut drawn = area.left;
+        if let Some(title) = self.pane_title(){
+            if focused{
+                Terminal::set_attribute(Attribute::Bold);
+            }
+            let title = text::truncate(&title, area.width);
+            drawn += text::width(&title);
+            write!(stdout(), "{title}")?;
+            Terminal::reset_attributes();
+        }
+
+        for ((i, sheet), (_, end)) in self.workbook.sheets.iter().enumerate().zip(self.tab_spans()){
+            if end > right{
+                break;
+            }
+            if i == self.workbook.active{
+                Terminal::set_bg_color(if focused { Color::White } else { Color::Grey });
+                Terminal::set_fg_color(Color::Black);
+            }
+            write!(stdout(), " {} ", sheet.name)?;
+            Terminal::reset_colors();
+            write!(stdout(), "|")?;
+            drawn = end;
+        }
+        write!(stdout(), "{}", " ".repeat(right.saturating_sub(drawn)))?;
+        Ok(())
+    }
+
+    fn draw_edit_line(&mut self) -> Result<(), std::io::Error>{
+        let Some(cell) = self.document.get_cell(&self.cell_position) else{
+            return Ok(());
+        };
+
+        let line = cell.val.to_string();
+        let mut room = self.terminal.size().width;
+        let sheet = &self.workbook.sheets[self.workbook.active].name;
+        for span in highlight::highlight(&line, sheet, |name| self.document.named_range(name)){
+            if room == 0{
+                break;
+            }
+            let piece = text::truncate(&line[span.start..span.end], room);
+            room = room.saturating_sub(text::width(&piece));
+
+            let (fg, bg) = span.kind.colors();
+            if let Some(fg) = fg{
+                Terminal::set_fg_color(fg);
+            }
+            if let Some(bg) = bg{
+                Terminal::set_bg_color(bg);
+            }
+            write!(stdout(), "{piece}")?;
+            Terminal::reset_colors();
         }
+        Ok(())
+    }
+
+    /// Ranges the formula of the current cell refers to, with their colors
+    fn references(&self) -> Vec<(Range, Color)>{
+        self.document.get_cell(&self.cell_position)
+            .map(|cell| {
+                let sheet = &self.workbook.sheets[self.workbook.active].name;
+                highlight::references(&cell.val.to_string(), sheet, |name| self.document.named_range(name))
+            })
+            .unwrap_or_default()
     }
 
-    fn draw_cell(&mut self, p: &Position) -> Result<(), std::io::Error>{
+    /// Draws a cell, `width` being less than the width of its column when it is cut by the edge of the pane
+    fn draw_cell(&self, p: &Position, width: usize, formats: &Formats, references: &[(Range, Color)], mark: Option<Color>) -> Result<(), std::io::Error>{
+        let cell = self.document.get_cell(p);
+        let mut style = cell.map(|c| c.style.clone()).unwrap_or_default();
+        if let Some(cell) = cell{
+            style.overlay(&formats.style(p, &cell.val));
+        }
+
         if self.cell_position.x == p.x && self.cell_position.y == p.y{
             Terminal::set_bg_color(Color::White);
             Terminal::set_fg_color(Color::Black);
+        }else if self.selected_range().is_some_and(|range| range.contains(p)){
+            Terminal::set_bg_color(Color::DarkGrey);
+        }else if self.search.as_ref().is_some_and(|search| cell.is_some_and(|c| c.val.to_string().contains(&search.query))){
+            Terminal::set_bg_color(Color::Yellow);
+            Terminal::set_fg_color(Color::Black);
+        }else if let Some((_, color)) = references.iter().find(|(range, _)| range.contains(p)){
+            Terminal::set_bg_color(*color);
+            Terminal::set_fg_color(Color::Black);
+        }else if let Some(color) = mark{
+            Terminal::set_bg_color(color);
+            Terminal::set_fg_color(Color::Black);
+        }else{
+            if let Some(fg) = style.fg{
+                Terminal::set_fg_color(fg);
+            }
+            if let Some(bg) = style.bg{
+                Terminal::set_bg_color(bg);
+            }
         }
 
-        if self.document.cell_exist(p){
-            let content = &self.document.rows[p.y].cells[p.x].render(9);
-            let len = content.len();
-            let margin_right: usize = 9_usize.saturating_sub(len);
-            write!(stdout(), "{}{}", &content, &" ".repeat(margin_right))?;
+        if style.bold{
+            Terminal::set_attribute(Attribute::Bold);
+        }
+        if style.italic{
+            Terminal::set_attribute(Attribute::Italic);
+        }
+        if style.underline{
+            Terminal::set_attribute(Attribute::Underlined);
+        }
+
+        if let Some(cell) = cell{
+            let content = &self.document.render_cell(cell, p.x, width);
+            let content = match style.align{
+                Align::Left => text::pad_right(content, width),
+                Align::Center => text::center(content, width),
+                Align::Right => text::pad_left(content, width),
+            };
+            write!(stdout(), "{content}")?;
         }else{
-            write!(stdout(), "{}", &" ".repeat(9))?;
+            write!(stdout(), "{}", &" ".repeat(width))?;
         }
 
+        Terminal::reset_attributes();
         Terminal::reset_colors();
         Ok(())
     }
 
-    fn draw_row(&mut self, y: usize) -> Result<(), std::io::Error>{
-        let num_col = self.terminal.size.width.saturating_sub(5) / 9;
-
+    /// Draws the row `y` on the screen line `line`
+    fn draw_row(&self, y: usize, line: usize, columns: &[usize], formats: &Formats, references: &[(Range, Color)]) -> Result<(), std::io::Error>{
+        let area = self.area();
+        Terminal::goto(&Position{x: area.left, y: line});
         if self.cell_position.y != y {
             Terminal::set_bg_color(Color::White);
             Terminal::set_fg_color(Color::Black);
@@ -350,34 +2000,74 @@ impl Editor{
         write!(stdout(), "      ")?;
         Terminal::reset_colors();
 
-        for x in 0..num_col{
-            self.draw_cell(&Position{x,y})?;
+        let difference = self.diff_side().and_then(|side| self.diff.as_ref()?.0.line(side, y));
+        let mut room = area.width.saturating_sub(6);
+        for x in columns{
+            let width = self.document.col_width(*x).min(room);
+            if width == 0{
+                break;
+            }
+            let p = Position{x: *x, y};
+            let mark = difference.and_then(|line| line.color(*x)).or_else(|| self.merge.as_ref()?.color(self.pane, &p));
+            self.draw_cell(&p, width, formats, references, mark)?;
+            room -= width;
         }
+        write!(stdout(), "{}", " ".repeat(room))?;
 
+        Terminal::goto(&Position{x: area.left, y: line});
         if self.cell_position.y != y {
             Terminal::set_bg_color(Color::White);
             Terminal::set_fg_color(Color::Black);
         }
-        write!(stdout(), "\r")?;
-        write!(stdout(), "  {}\r\n", &y)?;
+        write!(stdout(), "{} {}", difference.map_or(' ', Line::marker), y.saturating_add(1))?;
         Terminal::reset_colors();
         Ok(())
     }
 
-    fn draw_grid(&mut self) -> Result<(), std::io::Error>{
-        let size = self.terminal.size();
-
-        self.draw_header()?;
-        write!(stdout(), "\r\n")?;
-
-        Terminal::reset_colors();
+    /// Draws every pane, the focused one showing the selection and search matches
+    fn draw_panes(&mut self) -> Result<(), std::io::Error>{
+        let focused = self.pane;
+        self.draw_pane(true)?;
+
+        let selection = self.selection.take();
+        let search = self.search.take();
+        let mut result = Ok(());
+        for i in (0..self.panes.len()).filter(|i| *i != focused){
+            self.swap_pane(i);
+            result = self.draw_pane(false);
+            if result.is_err(){
+                break;
+            }
+        }
+        self.swap_pane(focused);
+        self.selection = selection;
+        self.search = search;
+        result
+    }
 
-        for i in self.offset.y..size.height.saturating_add(self.offset.y){
-            Terminal::clear_line();
-            self.draw_row(i)?;
-            // write!(stdout(), "\u{2502} {}\r\n", i/2)?;
+    /// Draws the tabs, column headers and rows of the pane being edited
+    fn draw_pane(&self, focused: bool) -> Result<(), std::io::Error>{
+        let area = self.area();
+        Terminal::goto(&Position{x: area.left, y: area.top});
+        self.draw_tabs(focused)?;
+
+        let columns = self.grid_columns();
+        Terminal::goto(&Position{x: area.left, y: area.top + 1});
+        self.draw_header(&columns)?;
+
+        let formats = Formats::new(&self.document);
+        let references = self.references();
+        let top = area.top + pane::HEADER_LINES;
+        for (i, y) in self.grid_rows().into_iter().enumerate(){
+            self.draw_row(y, top + i, &columns, &formats, &references)?;
         }
 
+        if let Some(separator) = area.left.checked_sub(1){
+            for line in area.top..top + area.rows{
+                Terminal::goto(&Position{x: separator, y: line});
+                write!(stdout(), "\u{2502}")?;
+            }
+        }
         Ok(())
     }
 
@@ -390,47 +2080,69 @@ impl Editor{
         let helper_message_len = helper_message.len();
 
         if self.status.message.is_empty(){
-            write!(stdout(), "Editing: {}", self.document.file_name.as_ref().unwrap_or(&"[No Name]".to_string()))?;
+            write!(stdout(), "Editing: {}", self.workbook.file_name.as_ref().unwrap_or(&"[No Name]".to_string()))?;
+            if !self.document.filters.is_empty(){
+                let shown = (0..self.document.len).filter(|y| self.row_visible(*y)).count();
+                write!(stdout(), " ({} of {} rows shown)", shown, self.document.len)?;
+            }
         }else{
             write!(stdout(), "{}", self.status.message)?;
         }
 
+        if self.pane_dirty(self.pane){
+            write!(stdout(), "  [+]")?;
+        }
+
+        if self.workbook.follow.is_some(){
+            write!(stdout(), "  [following, read-only]")?;
+        }
+
+        if let Some(search) = &self.search{
+            match search.current{
+                Some(current) => write!(stdout(), "  [match {} of {}]", current, search.total)?,
+                None => write!(stdout(), "  [{} matches]", search.total)?,
+            }
+        }
+
+        if let Some(merge) = &self.merge{
+            write!(stdout(), "  [{} conflict(s) left]", merge.unresolved())?;
+        }
+
+        if let Some(summary) = self.selection_summary(){
+            write!(stdout(), "  [{summary}]")?;
+        }
+
         if size.width > 2*helper_message_len{
             Terminal::goto(&Position{x: size.width.saturating_sub(helper_message_len), y:0});
             Terminal::set_bg_color(Color::White);
             Terminal::set_fg_color(Color::Black);
-            write!(stdout(), "{}", helper_message)?;
+            write!(stdout(), "{helper_message}")?;
             Terminal::reset_colors();
         }
 
         Ok(())
     }
 
-    fn draw_header(&mut self) -> Result<(), std::io::Error>{
-        let alphabet = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    fn draw_header(&self, columns: &[usize]) -> Result<(), std::io::Error>{
         let cols: usize = max(self.document.col_count(), self.cell_position.x.saturating_add(1));
 
-        let headers: Vec<String>= if self.header {
-            let col_titles = self.document.rows[0].cells.iter().map(|c| c.render(9)).collect::<Vec<String>>();
-            let lens = col_titles.iter().map(std::string::String::len).collect::<Vec<usize>>();
-            let margin = lens.iter().map(|l| 9_usize.saturating_sub(*l)/2).collect::<Vec<usize>>();
-            let headers_str = col_titles.iter().enumerate().map(|i| format!("{}{}{}", &" ".repeat(margin[i.0]),i.1, &" ".repeat(9_usize.saturating_sub(lens[i.0].saturating_add(margin[i.0]))))).collect::<Vec<String>>();
-            headers_str
-        }else{
-            let mut headers_str: Vec<String> = Vec::new();
-            let alphabet_chars = alphabet.chars().collect::<Vec<char>>();
-            for i in alphabet_chars.iter().take(cols){
-                let h_fmt = format!("    {}    ", i);
-                headers_str.push(h_fmt);
-            }
-            headers_str
-        };
         //Columns Index Margin
-        Terminal::clear_line();
         write!(stdout(), "      ")?;
 
-        for i in headers.iter().enumerate(){
-            if i.0 == self.cell_position.x{
+        let mut room = self.area().width.saturating_sub(6);
+        for x in columns.iter().copied().take_while(|x| *x < cols){
+            let width = self.document.col_width(x).min(room);
+            if width == 0{
+                break;
+            }
+            room -= width;
+            let title = if self.header{
+                self.document.get_cell(&Position{x, y: 0}).map(|c| c.render(0)).unwrap_or_default()
+            }else{
+                column_name(x)
+            };
+
+            if x == self.cell_position.x{
                 Terminal::set_bg_color(Color::Black);
                 Terminal::set_fg_color(Color::White);
             }else{
@@ -438,14 +2150,32 @@ impl Editor{
                 Terminal::set_fg_color(Color::Black);
             }
 
-            write!(stdout(), "{}", i.1)?;
+            if self.document.filters.is_filtered(x){
+                // Filter indicator on the last character of the header
+                write!(stdout(), "{}\u{25be}", text::center(&title, width.saturating_sub(1)))?;
+            }else{
+                write!(stdout(), "{}", text::center(&title, width))?;
+            }
         }
 
         Terminal::reset_colors();
+        write!(stdout(), "{}", " ".repeat(room))?;
         Ok(())
     }
 
-    pub fn prompt<C>(&mut self, message: &str, mut callback: C) -> Result<Option<String>, std::io::Error> where C: FnMut(&mut Self, KeyCode, &String){
+    /// Same as `prompt_input`, an empty answer being treated as canceled
+    ///
+    /// # Errors
+    /// Will return an error when the screen can't be drawn
+    pub fn prompt<C>(&mut self, message: &str, callback: C) -> Result<Option<String>, std::io::Error> where C: FnMut(&mut Self, KeyCode, &String){
+        Ok(self.prompt_input(message, callback)?.filter(|result| !result.is_empty()))
+    }
+
+    /// Asks for a line in the status bar, returns `None` when canceled with ESC
+    ///
+    /// # Errors
+    /// Will return an error when the screen can't be drawn
+    pub fn prompt_input<C>(&mut self, message: &str, mut callback: C) -> Result<Option<String>, std::io::Error> where C: FnMut(&mut Self, KeyCode, &String){
         let mut result = String::new();
         loop {
             self.status = Status{message:format!("{}{}", &message,&result)};
@@ -457,24 +2187,64 @@ impl Editor{
                     KeyCode::Backspace => {result.pop();},
                     KeyCode::Enter => break,
                     KeyCode::Esc => {
-                        result.truncate(0);
-                        break;
+                        callback(self,key, &String::new());
+                        return Ok(None);
                     },
                     _ => ()
-                };
+                }
                 callback(self,key, &result);
             }
         }
 
-        if result.is_empty(){
-            return Ok(None);
-        }
-
         Ok(Some(result))
     }
 }
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::terminal::Size;
+
+    /// Editor of `text` on an 80 columns screen with 10 lines of cells
+    fn editor(text: &str) -> Editor{
+        let mut document = Document::default();
+        document.replace_rows(text.lines().map(|line| Row::parse(line, ';')).collect());
+        let terminal = Terminal{size: Size{width: 80, height: 10}};
+        Editor::with_panes(vec![Workbook::new(None, document)], Split::Horizontal, false, terminal)
+    }
+
+    /// Column and row of the cell drawn at a place of the screen
+    fn hit_cell(editor: &Editor, column: usize, row: usize) -> Option<(usize, usize)>{
+        match editor.hit_test(column, row){
+            Some(GridHit::Cell(p)) => Some((p.x, p.y)),
+            _ => None,
+        }
+    }
 
-fn die(e: &std::io::Error){
-    Terminal::clear();
-    panic!("{}", e);
-}
\ No newline at end of file
+    #[test]
+    fn hit_test_finds_tabs_headers_and_cells(){
+        let editor = editor("a;b;c\n1;2;3\n4;5;6");
+        let grid_top = pane::TOP + pane::HEADER_LINES;
+        assert!(matches!(editor.hit_test(1, pane::TOP), Some(GridHit::Sheet(0))));
+        assert!(matches!(editor.hit_test(6 + 9, grid_top - 1), Some(GridHit::Column(1))));
+        assert!(matches!(editor.hit_test(0, grid_top + 2), Some(GridHit::Row(2))));
+        assert_eq!(hit_cell(&editor, 6, grid_top), Some((0, 0)));
+        assert_eq!(hit_cell(&editor, 6 + 9 + 8, grid_top + 1), Some((1, 1)));
+        assert_eq!(hit_cell(&editor, 6 + 9 + 9, grid_top + 1), Some((2, 1)));
+        assert!(editor.hit_test(6, grid_top + 10).is_none());
+    }
+
+    #[test]
+    fn hit_test_follows_scrolling_frozen_panes_and_widths(){
+        let mut editor = editor("a;b;c;d\n1;2;3;4\n5;6;7;8\n9;10;11;12");
+        let grid_top = pane::TOP + pane::HEADER_LINES;
+        editor.document.set_col_width(0, 4);
+        editor.frozen = Position{x: 1, y: 1};
+        editor.offset = Position{x: 2, y: 2};
+        assert_eq!(hit_cell(&editor, 6 + 3, grid_top), Some((0, 0)));
+        assert_eq!(hit_cell(&editor, 6 + 4, grid_top), Some((2, 0)));
+        assert_eq!(hit_cell(&editor, 6 + 4 + 9, grid_top + 1), Some((3, 2)));
+
+        editor.document.filters.set(Filter{column: 0, condition: Condition::parse("!=5").unwrap()});
+        assert_eq!(hit_cell(&editor, 6, grid_top + 1), Some((0, 3)));
+    }
+}
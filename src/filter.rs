@@ -0,0 +1,191 @@
+use regex::Regex;
+
+use crate::cell::DataType;
+use crate::Row;
+
+static EMPTY: DataType = DataType::Empty;
+
+pub enum Condition{
+    Equals(String),
+    NotEquals(String),
+    Contains(String),
+    Matches(Regex),
+    Less(f64),
+    LessOrEqual(f64),
+    Greater(f64),
+    GreaterOrEqual(f64),
+    Blank,
+    NotBlank,
+}
+
+impl Condition{
+    /// Parses a filter expression:
+    /// `=x`, `!=x`, `<n`, `<=n`, `>n`, `>=n`, `/regex/`, `blank`, `!blank`,
+    /// anything else keeps the cells containing the text, ignoring case
+    ///
+    /// # Errors
+    /// Will return an error message when the regex or the number can't be parsed
+    pub fn parse(expr: &str) -> Result<Self, String>{
+        let number = |s: &str| s.trim().parse::<f64>().map_err(|_| format!("Not a number: {}", s.trim()));
+        let condition = if expr == "blank"{
+            Condition::Blank
+        }else if expr == "!blank"{
+            Condition::NotBlank
+        }else if let Some(v) = expr.strip_prefix("!="){
+            Condition::NotEquals(v.to_string())
+        }else if let Some(v) = expr.strip_prefix("<="){
+            Condition::LessOrEqual(number(v)?)
+        }else if let Some(v) = expr.strip_prefix(">="){
+            Condition::GreaterOrEqual(number(v)?)
+        }else if let Some(v) = expr.strip_prefix('<'){
+            Condition::Less(number(v)?)
+        }else if let Some(v) = expr.strip_prefix('>'){
+            Condition::Greater(number(v)?)
+        }else if let Some(v) = expr.strip_prefix('='){
+            Condition::Equals(v.to_string())
+        }else if expr.len() > 1 && expr.starts_with('/') && expr.ends_with('/'){
+            let re = Regex::new(&expr[1..expr.len() - 1]).map_err(|e| e.to_string())?;
+            Condition::Matches(re)
+        }else{
+            Condition::Contains(expr.to_lowercase())
+        };
+        Ok(condition)
+    }
+
+    #[must_use]
+    pub fn matches(&self, val: &DataType) -> bool{
+        let text = val.to_string();
+        let number = text.trim().parse::<f64>().ok();
+        match self{
+            Condition::Equals(v) => equals(&text, v),
+            Condition::NotEquals(v) => !equals(&text, v),
+            Condition::Contains(v) => text.to_lowercase().contains(v.as_str()),
+            Condition::Matches(re) => re.is_match(&text),
+            Condition::Less(n) => number.is_some_and(|x| x < *n),
+            Condition::LessOrEqual(n) => number.is_some_and(|x| x <= *n),
+            Condition::Greater(n) => number.is_some_and(|x| x > *n),
+            Condition::GreaterOrEqual(n) => number.is_some_and(|x| x >= *n),
+            Condition::Blank => text.trim().is_empty(),
+            Condition::NotBlank => !text.trim().is_empty(),
+        }
+    }
+}
+
+/// Numbers are compared by value, text as is
+fn equals(text: &str, v: &str) -> bool{
+    match (text.trim().parse::<f64>(), v.trim().parse::<f64>()){
+        (Ok(x), Ok(y)) => (x - y).abs() < f64::EPSILON,
+        _ => text == v,
+    }
+}
+
+pub struct Filter{
+    pub column: usize,
+    pub condition: Condition,
+}
+
+/// Active filters, a row is shown when it passes all of them
+#[derive(Default)]
+pub struct Filters{
+    filters: Vec<Filter>,
+}
+
+impl Filters{
+    /// Sets the filter of a column, replacing the previous one
+    pub fn set(&mut self, filter: Filter){
+        self.remove(filter.column);
+        self.filters.push(filter);
+    }
+
+    pub fn remove(&mut self, column: usize){
+        self.filters.retain(|f| f.column != column);
+    }
+
+    /// Moves the filters from the column `at` one column to the right
+    pub fn insert_column(&mut self, at: usize){
+        for filter in self.filters.iter_mut().filter(|f| f.column >= at){
+            filter.column += 1;
+        }
+    }
+
+    /// Takes the filter of the column `at` away, moving the ones after it one column to the left
+    pub fn remove_column(&mut self, at: usize) -> Option<Filter>{
+        let removed = self.filters.iter().position(|f| f.column == at).map(|i| self.filters.remove(i));
+        for filter in self.filters.iter_mut().filter(|f| f.column > at){
+            filter.column -= 1;
+        }
+        removed
+    }
+
+    pub fn clear(&mut self){
+        self.filters.clear();
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool{
+        self.filters.is_empty()
+    }
+
+    #[must_use]
+    pub fn is_filtered(&self, column: usize) -> bool{
+        self.filters.iter().any(|f| f.column == column)
+    }
+
+    #[must_use]
+    pub fn matches(&self, row: &Row) -> bool{
+        self.filters.iter().all(|f| {
+            f.condition.matches(row.cells.get(f.column).map_or(&EMPTY, |c| &c.val))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn matches(expr: &str, text: &str) -> bool{
+        Condition::parse(expr).unwrap().matches(&DataType::String(text.to_string()))
+    }
+
+    #[test]
+    fn conditions_compare_text_and_numbers(){
+        assert!(matches("=abc", "abc") && !matches("=abc", "ABC"));
+        assert!(matches("=1", "1.0") && matches("!=1", "2") && !matches("!=abc", "abc"));
+        assert!(matches("<10", "9.5") && !matches("<10", "10") && matches("<=10", "10"));
+        assert!(matches(">-1", "0") && !matches(">-1", "-1") && matches(">=-1", "-1"));
+        assert!(!matches(">0", "abc") && !matches("<0", ""));
+        assert!(matches("late", "Very LATE") && !matches("late", "on time"));
+        assert!(matches("/^a.c$/", "abc") && !matches("/^a.c$/", "xabc"));
+        assert!(matches("blank", " ") && !matches("blank", "x"));
+        assert!(matches("!blank", "x") && !matches("!blank", ""));
+        assert!(Condition::parse("<abc").is_err());
+        assert!(Condition::parse("/(/").is_err());
+    }
+
+    #[test]
+    fn rows_pass_every_filter(){
+        let mut filters = Filters::default();
+        filters.set(Filter{column: 0, condition: Condition::parse("=x").unwrap()});
+        filters.set(Filter{column: 2, condition: Condition::parse("blank").unwrap()});
+        assert!(filters.matches(&Row::parse("x;1", ';')));
+        assert!(!filters.matches(&Row::parse("x;1;3", ';')));
+        assert!(!filters.matches(&Row::parse("y;1", ';')));
+
+        filters.set(Filter{column: 0, condition: Condition::parse("=y").unwrap()});
+        assert!(filters.matches(&Row::parse("y", ';')));
+        filters.remove(2);
+        assert!(filters.matches(&Row::parse("y;1;3", ';')));
+    }
+
+    #[test]
+    fn filters_follow_their_column(){
+        let mut filters = Filters::default();
+        filters.set(Filter{column: 1, condition: Condition::parse("=x").unwrap()});
+        filters.insert_column(0);
+        assert!(filters.is_filtered(2) && !filters.is_filtered(1));
+        assert!(filters.remove_column(0).is_none());
+        assert!(filters.is_filtered(1));
+        assert!(filters.remove_column(1).is_some());
+        assert!(filters.is_empty());
+    }
+}
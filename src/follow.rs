@@ -0,0 +1,148 @@
+//! Follow mode: a delimited file written by another program, such as a log, is read
+//! again as it grows, only the lines appended since the last read being parsed.
+
+use std::fs::{self, File};
+use std::io::{Error, Read, Seek, SeekFrom};
+use std::mem;
+
+use crate::Row;
+
+pub enum Change{
+    /// Lines were appended, the first one completing the partial last line when `replace_last`
+    Appended{rows: Vec<Row>, replace_last: bool},
+    /// The file got shorter, it was truncated or replaced and must be read again
+    Truncated,
+}
+
+pub struct Follower{
+    filename: String,
+    /// Bytes of the file read so far
+    offset: u64,
+    /// End of the file not ending with a new line yet
+    pending: Vec<u8>,
+    /// Whether the pending line is already shown as the last row
+    partial_shown: bool,
+}
+
+impl Follower{
+    /// Follows `filename` whose first `rows` lines were read, the last one maybe still being written
+    ///
+    /// # Errors
+    /// Will return an error when the file can't be read
+    pub fn new(filename: &str, rows: usize) -> Result<Self, Error>{
+        let content = fs::read(filename)?;
+        let mut offset = 0;
+        let mut pending = Vec::new();
+        for line in content.split_inclusive(|b| *b == b'\n').take(rows){
+            if line.ends_with(b"\n"){
+                offset += line.len();
+            }else{
+                pending = line.to_vec();
+            }
+        }
+        Ok(Self{
+            filename: filename.to_string(),
+            offset: (offset + pending.len()) as u64,
+            partial_shown: !pending.is_empty(),
+            pending,
+        })
+    }
+
+    /// Rows of the lines completed since the last read, `None` when there is none
+    ///
+    /// # Errors
+    /// Will return an error when the file can't be read
+    pub fn read(&mut self) -> Result<Option<Change>, Error>{
+        let mut file = File::open(&self.filename)?;
+        let len = file.metadata()?.len();
+        if len < self.offset{
+            return Ok(Some(Change::Truncated));
+        }
+        if len == self.offset{
+            return Ok(None);
+        }
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        self.offset += appended.len() as u64;
+        self.pending.extend(appended);
+
+        let Some(end) = self.pending.iter().rposition(|b| *b == b'\n') else{
+            return Ok(None);
+        };
+        let complete: Vec<u8> = self.pending.drain(..=end).collect();
+        let rows = String::from_utf8_lossy(&complete).lines().map(|line| Row::parse(line, ';')).collect();
+        Ok(Some(Change::Appended{rows, replace_last: mem::take(&mut self.partial_shown)}))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::env;
+    use std::io::Write;
+
+    fn file(name: &str, content: &str) -> String{
+        let path = env::temp_dir().join(format!("rexcel-follow-{}-{name}", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn append(path: &str, content: &str){
+        File::options().append(true).open(path).unwrap().write_all(content.as_bytes()).unwrap();
+    }
+
+    /// Lines of the rows appended, and whether the first one replaces the last row
+    fn appended(follower: &mut Follower) -> Option<(Vec<String>, bool)>{
+        match follower.read().unwrap(){
+            Some(Change::Appended{rows, replace_last}) => Some((rows.iter().map(|row| row.stringify(";")).collect(), replace_last)),
+            Some(Change::Truncated) => panic!("truncated"),
+            None => None,
+        }
+    }
+
+    #[test]
+    fn complete_lines_are_read_once(){
+        let path = file("complete", "a;1\n");
+        let mut follower = Follower::new(&path, 1).unwrap();
+        assert!(appended(&mut follower).is_none());
+        append(&path, "b;2\nc;3\n");
+        assert_eq!(appended(&mut follower), Some((vec!["b;2".to_string(), "c;3".to_string()], false)));
+        assert!(appended(&mut follower).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn partial_lines_wait_for_their_end(){
+        let path = file("partial", "a;1\n");
+        let mut follower = Follower::new(&path, 1).unwrap();
+        append(&path, "b;");
+        assert!(appended(&mut follower).is_none());
+        append(&path, "2\nc");
+        assert_eq!(appended(&mut follower), Some((vec!["b;2".to_string()], false)));
+        append(&path, ";3\n");
+        assert_eq!(appended(&mut follower), Some((vec!["c;3".to_string()], false)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_partial_line_shown_at_start_is_replaced_once_complete(){
+        let path = file("shown", "a;1\nb;");
+        let mut follower = Follower::new(&path, 2).unwrap();
+        append(&path, "2\nc;3\n");
+        assert_eq!(appended(&mut follower), Some((vec!["b;2".to_string(), "c;3".to_string()], true)));
+        append(&path, "d;4\n");
+        assert_eq!(appended(&mut follower), Some((vec!["d;4".to_string()], false)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn shorter_files_were_truncated(){
+        let path = file("truncated", "a;1\nb;2\n");
+        let mut follower = Follower::new(&path, 2).unwrap();
+        fs::write(&path, "x\n").unwrap();
+        assert!(matches!(follower.read(), Ok(Some(Change::Truncated))));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+//! Formulas (`=SUM(B2:B9) * 2`): their references follow the rows and columns inserted
+//! or deleted, a reference to cells all deleted becoming `#REF!`. Arithmetic on numbers,
+//! dates, date-times and durations (`=B2 + 30`, `=C2 - B2`) is computed to be shown.
+
+use crate::cell::DataType;
+use crate::highlight::{highlight, Kind};
+use crate::reference::{column_name, Range};
+use crate::temporal::{self, Duration};
+use crate::{Document, Position};
+
+/// Written in place of a reference to deleted cells
+pub const REF_ERROR: &str = "#REF!";
+
+/// Formulas referring to formulas deeper than this are not computed, which stops reference cycles
+const MAX_DEPTH: usize = 64;
+
+enum Token{
+    Number(f64),
+    Cell(Position),
+    Operator(char),
+    Open,
+    Close,
+}
+
+/// Value of `formula`: numbers, dates, date-times, durations and cells of `document` combined with
+/// `+`, `-`, `*`, `/` and parentheses, a date plus a number of days or a duration being a date, and
+/// a date minus a date a duration. `None` for anything else, such as a function or a text, the
+/// formula being shown as typed
+#[must_use]
+pub fn evaluate(document: &Document, formula: &str) -> Option<DataType>{
+    evaluate_at(document, formula, 0)
+}
+
+fn evaluate_at(document: &Document, formula: &str, depth: usize) -> Option<DataType>{
+    if depth > MAX_DEPTH{
+        return None;
+    }
+    let mut tokens = Vec::new();
+    for span in highlight(formula, "", |name| document.names.get(&name.to_uppercase()).copied()).into_iter().skip(1){
+        let text = &formula[span.start..span.end];
+        tokens.push(match span.kind{
+            Kind::Number => Token::Number(text.parse().ok()?),
+            Kind::Reference(range, _) if range.start == range.end => Token::Cell(range.start),
+            Kind::Operator if "+-*/".contains(text) => Token::Operator(text.chars().next()?),
+            Kind::Paren if text == "(" => Token::Open,
+            Kind::Paren => Token::Close,
+            Kind::Text if text.trim().is_empty() => continue,
+            _ => return None,
+        });
+    }
+
+    let mut parser = Parser{document, tokens, next: 0, depth};
+    let value = parser.sum()?;
+    (parser.next == parser.tokens.len()).then_some(value)
+}
+
+/// Reads the tokens of a formula by recursive descent, computing as it goes
+struct Parser<'a>{
+    document: &'a Document,
+    tokens: Vec<Token>,
+    next: usize,
+    /// Formulas being computed that refer to this one
+    depth: usize,
+}
+
+impl Parser<'_>{
+    fn operator(&mut self, operators: &str) -> Option<char>{
+        match self.tokens.get(self.next){
+            Some(Token::Operator(c)) if operators.contains(*c) => {
+                self.next += 1;
+                Some(*c)
+            },
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Option<DataType>{
+        let mut value = self.product()?;
+        while let Some(operator) = self.operator("+-"){
+            let right = self.product()?;
+            value = match (value.as_number(), right.as_number(), operator){
+                (Some(a), Some(b), '+') => DataType::Float(a + b),
+                (Some(a), Some(b), _) => DataType::Float(a - b),
+                (_, _, '+') => temporal::add(&value, &right)?,
+                _ => temporal::sub(&value, &right)?,
+            };
+        }
+        Some(value)
+    }
+
+    fn product(&mut self) -> Option<DataType>{
+        let mut value = self.unary()?;
+        while let Some(operator) = self.operator("*/"){
+            let (a, b) = (value.as_number()?, self.unary()?.as_number()?);
+            value = DataType::Float(if operator == '*' { a * b } else { Some(a / b).filter(|_| b != 0.0)? });
+        }
+        Some(value)
+    }
+
+    fn unary(&mut self) -> Option<DataType>{
+        match self.operator("+-"){
+            Some('-') => match self.unary()?{
+                DataType::Duration(d) => Some(DataType::Duration(Duration{delta: -d.delta, style: d.style})),
+                value => Some(DataType::Float(-value.as_number()?)),
+            },
+            Some(_) => self.unary(),
+            None => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> Option<DataType>{
+        let token = self.tokens.get(self.next)?;
+        self.next += 1;
+        match token{
+            Token::Number(n) => Some(DataType::Float(*n)),
+            Token::Cell(p) => self.cell(p),
+            Token::Open => {
+                let value = self.sum()?;
+                matches!(self.tokens.get(self.next), Some(Token::Close)).then_some(())?;
+                self.next += 1;
+                Some(value)
+            },
+            Token::Operator(_) | Token::Close => None,
+        }
+    }
+
+    /// Value of the cell `p`, an empty cell counting as 0
+    fn cell(&self, p: &Position) -> Option<DataType>{
+        match self.document.get_cell(p).map(|cell| &cell.val){
+            None | Some(DataType::Empty) => Some(DataType::Float(0.0)),
+            Some(DataType::String(text)) if text.starts_with('=') => evaluate_at(self.document, text, self.depth + 1),
+            Some(DataType::String(text)) => text.trim().parse().ok().map(DataType::Float),
+            Some(DataType::Bool(_)) => None,
+            Some(value) => Some(value.clone()),
+        }
+    }
+}
+
+/// `formula` with each reference to its own sheet moved to the range `moved` gives, `None`
+/// meaning its cells were deleted, or `None` when no reference moved
+pub fn move_references<F>(formula: &str, moved: F) -> Option<String> where F: Fn(Range) -> Option<Range>{
+    let mut result = String::new();
+    let mut copied = 0;
+    for span in highlight(formula, "", |_| None){
+        let Kind::Reference(range, _) = span.kind else{
+            continue;
+        };
+        let target = moved(range);
+        if target == Some(range){
+            continue;
+        }
+        result.push_str(&formula[copied..span.start]);
+        match target{
+            Some(target) => result.push_str(&write_reference(&formula[span.start..span.end], target)),
+            None => result.push_str(REF_ERROR),
+        }
+        copied = span.end;
+    }
+    if copied == 0{
+        return None;
+    }
+    result.push_str(&formula[copied..]);
+    Some(result)
+}
+
+/// The reference `reference` (`B2`, `$B$2:C9`) pointing to `range` instead, its `$` signs kept
+fn write_reference(reference: &str, range: Range) -> String{
+    let write = |cell: &str, p: Position| -> String{
+        let fixed_column = if cell.starts_with('$') { "$" } else { "" };
+        let fixed_row = if cell.trim_start_matches('$').contains('$') { "$" } else { "" };
+        format!("{fixed_column}{}{fixed_row}{}", column_name(p.x), p.y + 1)
+    };
+    match reference.split_once(':'){
+        Some((start, end)) => format!("{}:{}", write(start, range.start), write(end, range.end)),
+        None => write(reference, range.start),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::Row;
+
+    fn document(text: &str) -> Document{
+        let mut document = Document::default();
+        document.replace_rows(text.lines().map(|line| Row::parse(line, ';')).collect());
+        document
+    }
+
+    fn computed(document: &Document, formula: &str) -> Option<String>{
+        evaluate(document, formula).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn numbers_follow_the_precedence_of_operators(){
+        let document = document("2;3;=A1*B1");
+        assert_eq!(computed(&document, "=1 + 2 * 3").as_deref(), Some("7"));
+        assert_eq!(computed(&document, "=(1 + 2) * -3").as_deref(), Some("-9"));
+        assert_eq!(computed(&document, "=A1 / B1 * 3").as_deref(), Some("2"));
+        assert_eq!(computed(&document, "=C1 + D1").as_deref(), Some("6"));
+        assert_eq!(computed(&document, "=A1 / 0"), None);
+    }
+
+    #[test]
+    fn dates_and_durations_add_up(){
+        let document = document("2024-01-30;2024-03-01;1:30:00;2024-01-30T08:00:00");
+        assert_eq!(computed(&document, "=A1 + 2").as_deref(), Some("2024-02-01"));
+        assert_eq!(computed(&document, "=B1 - 1").as_deref(), Some("2024-02-29"));
+        assert_eq!(computed(&document, "=B1 - A1").as_deref(), Some("P31D"));
+        assert_eq!(computed(&document, "=C1 + C1").as_deref(), Some("3:00:00"));
+        assert_eq!(computed(&document, "=D1 + C1").as_deref(), Some("2024-01-30T09:30:00"));
+        assert_eq!(computed(&document, "=A1 * 2"), None);
+    }
+
+    #[test]
+    fn other_formulas_are_not_computed(){
+        let document = document("a;=B1+1;=A1");
+        assert_eq!(computed(&document, "=SUM(A1:A3)"), None);
+        assert_eq!(computed(&document, "=A1 + 1"), None);
+        assert_eq!(computed(&document, "=\"x\" & 1"), None);
+        assert_eq!(computed(&document, "=1 +"), None);
+        // B1 refers to itself
+        assert_eq!(computed(&document, "=B1"), None);
+    }
+
+    fn rows_deleted(formula: &str, at: usize) -> Option<String>{
+        move_references(formula, |range| range.row_deleted(at))
+    }
+
+    #[test]
+    fn references_follow_inserted_rows(){
+        let inserted = |formula: &str| move_references(formula, |range| Some(range.row_inserted(2)));
+        assert_eq!(inserted("=SUM(B2:B9) * A5").as_deref(), Some("=SUM(B2:B10) * A6"));
+        assert_eq!(inserted("=$A$7+A$1+$C9").as_deref(), Some("=$A$8+A$1+$C10"));
+        assert_eq!(inserted("=A1+B2"), None);
+    }
+
+    #[test]
+    fn references_to_deleted_cells_become_errors(){
+        assert_eq!(rows_deleted("=A3*2+B5", 2).as_deref(), Some("=#REF!*2+B4"));
+        assert_eq!(rows_deleted("=SUM(A1:A9)", 2).as_deref(), Some("=SUM(A1:A8)"));
+        assert_eq!(move_references("=C1+D1", |range| range.column_deleted(2)).as_deref(), Some("=#REF!+C1"));
+    }
+
+    #[test]
+    fn strings_names_and_other_sheets_stay(){
+        assert_eq!(rows_deleted("=\"A5\" & Sheet2!A5 & total", 0), None);
+    }
+}
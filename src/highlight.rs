@@ -0,0 +1,245 @@
+//! Highlighting of the edit line: formulas (`=SUM(B2:B9) * 2`) are split into
+//! functions, references, strings, numbers and operators, plain values are
+//! colored by their type.
+
+use crossterm::style::Color;
+
+use crate::reference::{parse_cell, Range};
+
+/// Colors given in turn to the ranges a formula refers to
+const PALETTE: [Color; 6] = [Color::Blue, Color::Magenta, Color::Green, Color::Cyan, Color::Red, Color::DarkYellow];
+
+#[derive(Clone, Copy)]
+pub enum Kind{
+    Text,
+    Function,
+    Reference(Range, Color),
+    /// Reference to another sheet, not tinted in the grid
+    SheetReference(Color),
+    String,
+    Number,
+    Bool,
+    Operator,
+    Paren,
+    /// Parenthesis without its pair, or string never closed
+    Unmatched,
+}
+
+impl Kind{
+    /// Foreground and background used to draw the kind, `None` keeping the default
+    #[must_use]
+    pub fn colors(self) -> (Option<Color>, Option<Color>){
+        match self{
+            Kind::Text | Kind::Operator => (None, None),
+            Kind::Function => (Some(Color::Yellow), None),
+            Kind::Reference(_, color) | Kind::SheetReference(color) => (Some(color), None),
+            Kind::String => (Some(Color::DarkGreen), None),
+            Kind::Number | Kind::Bool => (Some(Color::DarkCyan), None),
+            Kind::Paren => (Some(Color::Grey), None),
+            Kind::Unmatched => (Some(Color::White), Some(Color::Red)),
+        }
+    }
+}
+
+/// Part of the input, `start..end` being a byte range
+pub struct Span{
+    pub start: usize,
+    pub end: usize,
+    pub kind: Kind,
+}
+
+/// Splits `input` into highlighted spans, `sheet` being the sheet holding it
+/// and `resolve` giving the range of a name
+pub fn highlight<F>(input: &str, sheet: &str, resolve: F) -> Vec<Span> where F: Fn(&str) -> Option<Range>{
+    let Some(formula) = input.strip_prefix('=') else{
+        let kind = if input.trim().parse::<f64>().is_ok(){
+            Kind::Number
+        }else if input == "true" || input == "false"{
+            Kind::Bool
+        }else{
+            Kind::Text
+        };
+        return vec![Span{start: 0, end: input.len(), kind}];
+    };
+
+    let mut spans = vec![Span{start: 0, end: 1, kind: Kind::Operator}];
+    let mut ranges: Vec<String> = Vec::new();
+    let mut parens = Vec::new();
+    let mut rest = formula;
+    while let Some(c) = rest.chars().next(){
+        let start = input.len() - rest.len();
+        let (len, kind) = if c == '"'{
+            match rest[1..].find('"'){
+                Some(end) => (end + 2, Kind::String),
+                None => (rest.len(), Kind::Unmatched),
+            }
+        }else if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())){
+            (rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len()), Kind::Number)
+        }else if let Some((qualifier, len)) = sheet_qualifier(rest){
+            let target = &rest[qualifier..];
+            match reference(target, name_len(target)){
+                Some((reference_len, range)) => {
+                    let name = rest[..qualifier - 1].trim_matches('\'');
+                    let len = qualifier + reference_len;
+                    if name.eq_ignore_ascii_case(sheet){
+                        (len, Kind::Reference(range, color(&mut ranges, range.to_string())))
+                    }else{
+                        (len, Kind::SheetReference(color(&mut ranges, format!("{}!{range}", name.to_uppercase()))))
+                    }
+                },
+                None => (len, Kind::Text),
+            }
+        }else if is_name_start(c){
+            let len = name_len(rest);
+            let name = &rest[..len];
+            if rest[len..].starts_with('('){
+                (len, Kind::Function)
+            }else if let Some((len, range)) = reference(rest, len){
+                (len, Kind::Reference(range, color(&mut ranges, range.to_string())))
+            }else if let Some(range) = resolve(name){
+                (len, Kind::Reference(range, color(&mut ranges, range.to_string())))
+            }else if name.eq_ignore_ascii_case("true") || name.eq_ignore_ascii_case("false"){
+                (len, Kind::Bool)
+            }else{
+                (len, Kind::Text)
+            }
+        }else if c == '('{
+            parens.push(spans.len());
+            (1, Kind::Paren)
+        }else if c == ')'{
+            (1, if parens.pop().is_some() { Kind::Paren } else { Kind::Unmatched })
+        }else if "+-*/^&=<>,;:%".contains(c){
+            (c.len_utf8(), Kind::Operator)
+        }else{
+            (c.len_utf8(), Kind::Text)
+        };
+        spans.push(Span{start, end: start + len, kind});
+        rest = &rest[len..];
+    }
+
+    for i in parens{
+        spans[i].kind = Kind::Unmatched;
+    }
+    spans
+}
+
+/// Ranges of `sheet` referred to by `input` with their colors, each range once
+pub fn references<F>(input: &str, sheet: &str, resolve: F) -> Vec<(Range, Color)> where F: Fn(&str) -> Option<Range>{
+    let mut found: Vec<(Range, Color)> = Vec::new();
+    for span in highlight(input, sheet, resolve){
+        if let Kind::Reference(range, color) = span.kind{
+            if !found.iter().any(|(r, _)| r.to_string() == range.to_string()){
+                found.push((range, color));
+            }
+        }
+    }
+    found
+}
+
+fn is_name_start(c: char) -> bool{
+    c.is_ascii_alphabetic() || c == '_' || c == '$'
+}
+
+/// Length of the `Sheet2!` or `'Q1 sales'!` starting `s`, with the length of the sheet name alone
+fn sheet_qualifier(s: &str) -> Option<(usize, usize)>{
+    let len = if let Some(quoted) = s.strip_prefix('\''){
+        quoted.find('\'')? + 2
+    }else if s.starts_with(is_name_start){
+        name_len(s)
+    }else{
+        return None;
+    };
+    s[len..].starts_with('!').then_some((len + 1, len))
+}
+
+fn name_len(s: &str) -> usize{
+    s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$')).unwrap_or(s.len())
+}
+
+/// Cell or range reference at the start of `s`, `len` being the length of its first name,
+/// `$` signs being ignored
+fn reference(s: &str, len: usize) -> Option<(usize, Range)>{
+    let start = parse_cell(&s[..len].replace('$', ""))?;
+    if let Some(rest) = s[len..].strip_prefix(':'){
+        let end_len = name_len(rest);
+        if let Some(end) = parse_cell(&rest[..end_len].replace('$', "")){
+            return Some((len + 1 + end_len, Range::new(start, end)));
+        }
+    }
+    Some((len, Range::new(start, start)))
+}
+
+/// Color of the range named `key`, the same range always getting the same color
+fn color(ranges: &mut Vec<String>, key: String) -> Color{
+    let index = ranges.iter().position(|r| *r == key).unwrap_or_else(|| {
+        ranges.push(key);
+        ranges.len() - 1
+    });
+    PALETTE[index % PALETTE.len()]
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::reference::parse_range;
+
+    /// Text and kind of each span of `input` typed in `Sheet1`, where `total` names `C1:C9`
+    fn spans(input: &str) -> Vec<(String, String)>{
+        let resolve = |name: &str| (name == "total").then(|| parse_range("C1:C9").unwrap());
+        highlight(input, "Sheet1", resolve).into_iter().filter(|span| input[span.start..span.end].trim() != "").map(|span| {
+            let kind = match span.kind{
+                Kind::Text => "text".to_string(),
+                Kind::Function => "function".to_string(),
+                Kind::Reference(range, _) => format!("reference {range}"),
+                Kind::SheetReference(_) => "sheet reference".to_string(),
+                Kind::String => "string".to_string(),
+                Kind::Number => "number".to_string(),
+                Kind::Bool => "bool".to_string(),
+                Kind::Operator => "operator".to_string(),
+                Kind::Paren => "paren".to_string(),
+                Kind::Unmatched => "unmatched".to_string(),
+            };
+            (input[span.start..span.end].to_string(), kind)
+        }).collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)>{
+        expected.iter().map(|(text, kind)| ((*text).to_string(), (*kind).to_string())).collect()
+    }
+
+    #[test]
+    fn plain_values_get_a_single_span(){
+        assert_eq!(spans(" 12.5"), pairs(&[(" 12.5", "number")]));
+        assert_eq!(spans("true"), pairs(&[("true", "bool")]));
+        assert_eq!(spans("SUM(A1)"), pairs(&[("SUM(A1)", "text")]));
+    }
+
+    #[test]
+    fn formulas_are_split_into_tokens(){
+        assert_eq!(spans("=SUM(B2:$B$9) * 2.5 & \"x)\""), pairs(&[
+            ("=", "operator"), ("SUM", "function"), ("(", "paren"), ("B2:$B$9", "reference B2:B9"), (")", "paren"),
+            ("*", "operator"), ("2.5", "number"), ("&", "operator"), ("\"x)\"", "string"),
+        ]));
+        assert_eq!(spans("=total+TRUE+foo"), pairs(&[
+            ("=", "operator"), ("total", "reference C1:C9"), ("+", "operator"), ("TRUE", "bool"), ("+", "operator"), ("foo", "text"),
+        ]));
+        assert_eq!(spans("=Sheet1!A1+'Q1 sales'!B2"), pairs(&[
+            ("=", "operator"), ("Sheet1!A1", "reference A1:A1"), ("+", "operator"), ("'Q1 sales'!B2", "sheet reference"),
+        ]));
+    }
+
+    #[test]
+    fn unmatched_parentheses_and_strings_stand_out(){
+        assert_eq!(spans("=(1))"), pairs(&[("=", "operator"), ("(", "paren"), ("1", "number"), (")", "paren"), (")", "unmatched")]));
+        assert_eq!(spans("=((1)"), pairs(&[("=", "operator"), ("(", "unmatched"), ("(", "paren"), ("1", "number"), (")", "paren")]));
+        assert_eq!(spans("=\"abc"), pairs(&[("=", "operator"), ("\"abc", "unmatched")]));
+    }
+
+    #[test]
+    fn each_range_is_listed_once_with_its_color(){
+        let references = references("=A1+B2:B3+A1+Sheet2!A1", "Sheet1", |_| None);
+        let ranges: Vec<String> = references.iter().map(|(range, _)| range.to_string()).collect();
+        assert_eq!(ranges, ["A1:A1", "B2:B3"]);
+        assert!(references[0].1 == PALETTE[0] && references[1].1 == PALETTE[1]);
+    }
+}
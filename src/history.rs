@@ -0,0 +1,96 @@
+use crate::conditional::Rule;
+use crate::filter::Filter;
+use crate::reference::Range;
+use crate::style::{NumberFormat, Style};
+use crate::{Cell, Document, Position, Row};
+
+/// What deleting a row or a column changed elsewhere, kept to be put back
+#[derive(Default)]
+pub struct Ranges{
+    /// Named ranges the deletion moved, shrank or dropped, with their previous range
+    pub names: Vec<(String, Range)>,
+    /// Conditional formats the deletion moved or shrank, by index, with their previous range
+    pub moved_rules: Vec<(usize, Range)>,
+    /// Conditional formats covering only the deleted cells, by index
+    pub dropped_rules: Vec<(usize, Rule)>,
+    /// Formulas whose references the deletion changed, where they are once it is done, with their previous text
+    pub formulas: Vec<(Position, String)>,
+}
+
+/// A deleted column, kept to be put back
+pub struct Column{
+    /// Cell of each row, `None` for the rows too short to have one
+    pub cells: Vec<Option<Cell>>,
+    pub width: usize,
+    pub format: Option<NumberFormat>,
+    pub filter: Option<Filter>,
+    pub ranges: Ranges,
+}
+
+/// A reversible change to a `Document`.
+/// Applying an edit with `Document::apply` returns the edit that reverts it.
+pub enum Edit{
+    InsertRow(usize),
+    DeleteRow(usize),
+    RestoreRow(usize, Row, Ranges),
+    InsertColumn(usize),
+    DeleteColumn(usize),
+    RestoreColumn(usize, Column),
+    /// Moves the row at index `order[i]` to index `i`
+    Reorder(Vec<usize>),
+    /// Replaces the given number of rows from an index by other rows
+    ReplaceRows(usize, usize, Vec<Row>),
+    /// Overwrites the cells at the given positions
+    SetCells(Vec<(Position, Cell)>),
+    /// Overwrites the style of the cells at the given positions, keeping their value
+    SetStyles(Vec<(Position, Style)>),
+}
+
+impl Edit{
+    /// Position the cursor should land on once the edit is applied
+    #[must_use]
+    pub fn focus(&self, from: Position) -> Position{
+        match self{
+            Edit::InsertRow(y) | Edit::DeleteRow(y) | Edit::RestoreRow(y, _, _) | Edit::ReplaceRows(y, _, _) => Position{x: from.x, y: *y},
+            Edit::InsertColumn(x) | Edit::DeleteColumn(x) | Edit::RestoreColumn(x, _) => Position{x: *x, y: from.y},
+            Edit::Reorder(_) => from,
+            Edit::SetCells(cells) => cells.first().map_or(from, |(p, _)| *p),
+            Edit::SetStyles(styles) => styles.first().map_or(from, |(p, _)| *p),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct History{
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History{
+    /// Number of edits that can be undone
+    #[must_use]
+    pub fn depth(&self) -> usize{
+        self.undo.len()
+    }
+
+    pub fn record(&mut self, inverse: Edit){
+        self.undo.push(inverse);
+        self.redo.clear();
+    }
+
+    /// Reverts the last recorded edit and returns the edit that was applied
+    pub fn undo(&mut self, document: &mut Document) -> Option<&Edit>{
+        let edit = self.undo.pop()?;
+        let inverse = document.apply(edit)?;
+        self.redo.push(inverse);
+        self.redo.last()
+    }
+
+    /// Re-applies the last undone edit and returns the edit that was applied
+    pub fn redo(&mut self, document: &mut Document) -> Option<&Edit>{
+        let edit = self.redo.pop()?;
+        let inverse = document.apply(edit)?;
+        self.undo.push(inverse);
+        self.undo.last()
+    }
+}
@@ -7,6 +7,27 @@ mod editor;
 mod cell;
 mod row;
 mod document;
+mod history;
+mod reference;
+mod sort;
+mod filter;
+mod matcher;
+mod sidecar;
+mod text;
+mod style;
+mod conditional;
+mod highlight;
+mod formula;
+mod temporal;
+mod workbook;
+mod spreadsheet;
+mod pane;
+mod diff;
+mod merge;
+mod swap;
+mod atomic;
+mod stamp;
+mod follow;
 
 use editor::Editor;
 pub use editor::Position;
@@ -15,8 +36,110 @@ pub use terminal::Terminal;
 pub use cell::Cell;
 pub use row::Row;
 pub use document::Document;
+pub use history::{Edit, History};
+
+use std::env;
+use std::process;
+
+use diff::{Diff, Pairing};
+use merge::Merge;
+use workbook::Workbook;
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.contains(&"--diff".to_string()){
+        process::exit(print_diff(&args));
+    }
+    if args.contains(&"--merge".to_string()){
+        process::exit(merge_files(&args));
+    }
+
     let mut editor = Editor::new().unwrap();
     editor.run();
 }
+
+/// `rexcel --diff old.csv new.csv [--key=B] [--header]` prints the differences of the first sheets
+/// without opening the editor, exiting with 0 when there is none, 1 when there are some and 2 on error
+fn print_diff(args: &[String]) -> i32{
+    let files: Vec<&String> = args.iter().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    let [old, new] = files.as_slice() else{
+        eprintln!("Usage: rexcel --diff old.csv new.csv [--key=B] [--header]");
+        return 2;
+    };
+    let pairing = match args.iter().find_map(|arg| arg.strip_prefix("--key=")){
+        Some(key) => {
+            let Some(x) = reference::column_index(key) else{
+                eprintln!("Invalid key column: {key}");
+                return 2;
+            };
+            Pairing::Key(x)
+        },
+        None => Pairing::Position,
+    };
+
+    let open = |filename: &str| Workbook::open(filename).map(|mut workbook| workbook.sheets.swap_remove(0).document);
+    let (old_document, new_document) = match (open(old), open(new)){
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Error opening file: {e}");
+            return 2;
+        },
+    };
+
+    let fixed = usize::from(args.contains(&"--header".to_string()));
+    let diff = Diff::new(&old_document, &new_document, pairing, fixed);
+    println!("--- {old}\n+++ {new}");
+    print!("{}", diff.report(&old_document, &new_document));
+    i32::from(!diff.lines.is_empty())
+}
+
+/// `rexcel --merge base.csv ours.csv theirs.csv [merged.csv]` merges the changes made to the first sheet
+/// of base in ours and theirs into merged (ours by default). The conflicts are resolved in the editor,
+/// exiting with 0 once the merged file is saved without conflict, 1 when it isn't and 2 on error.
+/// Git runs it as a merge tool with `rexcel --merge "$BASE" "$LOCAL" "$REMOTE" "$MERGED"`.
+fn merge_files(args: &[String]) -> i32{
+    let files: Vec<&String> = args.iter().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    let [base, ours, theirs, rest @ ..] = files.as_slice() else{
+        eprintln!("Usage: rexcel --merge base.csv ours.csv theirs.csv [merged.csv]");
+        return 2;
+    };
+    let output = rest.first().unwrap_or(ours);
+
+    let workbooks = [base, ours, ours, theirs].map(|filename| Workbook::open(filename));
+    let [Ok(base), Ok(ours), Ok(mut merged), Ok(theirs)] = workbooks else{
+        for e in workbooks.iter().filter_map(|workbook| workbook.as_ref().err()){
+            eprintln!("Error opening file: {e}");
+        }
+        return 2;
+    };
+
+    let (rows, conflicts) = merge::merge(&base.sheets[0].document, &ours.sheets[0].document, &theirs.sheets[0].document);
+    // The merged file keeps the layout of ours, without replacing it
+    merged.sheets.truncate(1);
+    merged.set_file_name((*output).clone());
+    merged.sheets[0].document.replace_rows(rows);
+
+    if conflicts.is_empty(){
+        return match merged.save(){
+            Ok(()) => {
+                println!("Merged without conflict into {output}");
+                0
+            },
+            Err(e) => {
+                eprintln!("Error saving file: {e}");
+                2
+            },
+        };
+    }
+
+    let merge = Merge::new(conflicts);
+    let mut editor = match Editor::for_merge(ours, merged, theirs, merge){
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("{e}");
+            return 2;
+        },
+    };
+    editor.run();
+    i32::from(!editor.merge_done())
+}
@@ -0,0 +1,129 @@
+use regex::{NoExpand, Regex, RegexBuilder};
+
+use crate::reference::Range;
+use crate::Position;
+
+#[derive(Default, Clone, Copy)]
+pub struct MatchOptions{
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub whole_cell: bool,
+}
+
+impl MatchOptions{
+    /// Reads options from flags: `r` regex, `c` match case, `w` whole cell
+    #[must_use]
+    pub fn from_flags(flags: &str) -> Self{
+        Self{
+            regex: flags.contains('r'),
+            case_sensitive: flags.contains('c'),
+            whole_cell: flags.contains('w'),
+        }
+    }
+}
+
+/// Cells a search or a replace applies to
+#[derive(Clone, Copy)]
+pub enum Scope{
+    All,
+    Column(usize),
+    Range(Range),
+}
+
+impl Scope{
+    #[must_use]
+    pub fn contains(&self, p: &Position) -> bool{
+        match self{
+            Scope::All => true,
+            Scope::Column(x) => p.x == *x,
+            Scope::Range(range) => range.contains(p),
+        }
+    }
+}
+
+pub struct Matcher{
+    re: Regex,
+    options: MatchOptions,
+}
+
+impl Matcher{
+    /// # Errors
+    /// Will return an error when the query is not a valid regex
+    pub fn new(query: &str, options: MatchOptions) -> Result<Self, regex::Error>{
+        let pattern = if options.regex{
+            query.to_string()
+        }else{
+            regex::escape(query)
+        };
+        let pattern = if options.whole_cell{
+            format!("^(?:{pattern})$")
+        }else{
+            pattern
+        };
+
+        let re = RegexBuilder::new(&pattern).case_insensitive(!options.case_sensitive).build()?;
+        Ok(Self{re, options})
+    }
+
+    #[must_use]
+    pub fn is_match(&self, text: &str) -> bool{
+        self.re.is_match(text)
+    }
+
+    #[must_use]
+    pub fn count(&self, text: &str) -> usize{
+        self.re.find_iter(text).count()
+    }
+
+    /// Replaces every match in `text`, `$1` or `${name}` expanding to capture groups in regex mode
+    #[must_use]
+    pub fn replace(&self, text: &str, replacement: &str) -> String{
+        if self.options.regex{
+            self.re.replace_all(text, replacement).into_owned()
+        }else{
+            self.re.replace_all(text, NoExpand(replacement)).into_owned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn matcher(query: &str, flags: &str) -> Matcher{
+        Matcher::new(query, MatchOptions::from_flags(flags)).unwrap()
+    }
+
+    #[test]
+    fn plain_queries_match_as_text_ignoring_case(){
+        let plain = matcher("a.b", "");
+        assert!(plain.is_match("xA.By") && !plain.is_match("axb"));
+        assert_eq!(plain.count("a.b A.B"), 2);
+        assert!(!matcher("a.b", "c").is_match("A.B"));
+        assert_eq!(matcher("$", "").replace("1$ 2$", "€$1"), "1€$1 2€$1");
+    }
+
+    #[test]
+    fn regex_queries_expand_capture_groups(){
+        let regex = matcher(r"(\d+)-(\d+)", "r");
+        assert!(regex.is_match("10-20") && !regex.is_match("10_20"));
+        assert_eq!(regex.replace("1-2, 3-4", "$2-$1"), "2-1, 4-3");
+        assert_eq!(matcher("(?P<n>x)", "r").replace("axb", "[${n}]"), "a[x]b");
+        assert!(Matcher::new("(", MatchOptions::from_flags("r")).is_err());
+    }
+
+    #[test]
+    fn whole_cell_matches_the_entire_text(){
+        assert!(matcher("abc", "w").is_match("ABC") && !matcher("abc", "w").is_match("abcd"));
+        assert!(matcher("a|b", "rw").is_match("b") && !matcher("a|b", "rw").is_match("ab"));
+        assert_eq!(matcher("abc", "wc").replace("abc", "x"), "x");
+    }
+
+    #[test]
+    fn scopes_contain_their_cells(){
+        assert!(Scope::All.contains(&Position{x: 5, y: 5}));
+        assert!(Scope::Column(1).contains(&Position{x: 1, y: 9}) && !Scope::Column(1).contains(&Position{x: 0, y: 9}));
+        let range = Scope::Range(crate::reference::parse_range("B2:C3").unwrap());
+        assert!(range.contains(&Position{x: 2, y: 1}) && !range.contains(&Position{x: 0, y: 1}));
+    }
+}
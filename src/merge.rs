@@ -0,0 +1,505 @@
+//! Three-way merge of two copies of a document changed from the same base. Rows are
+//! lined up like diff3 does with lines, then the changed rows are merged cell by cell:
+//! a cell changed on one side only takes that change, a cell changed differently on
+//! both sides is a conflict, left with our value until one is picked. Rows changed on both
+//! sides into a different number of rows, such as a row deleted on one side and changed on
+//! the other, cannot be paired: the rows are a conflict as a whole, our rows being kept (or
+//! theirs when ours were deleted) until a side is picked.
+
+use std::ops::Range;
+
+use crossterm::style::Color;
+
+use crate::Cell;
+use crate::Document;
+use crate::Position;
+use crate::Row;
+
+/// Panes of the merge, left to right
+pub const OURS: usize = 0;
+pub const MERGED: usize = 1;
+pub const THEIRS: usize = 2;
+
+/// Rows left to line up above which rows are paired by position instead, the alignment being quadratic
+const MAX_ALIGNED: usize = 4_000_000;
+
+pub struct Conflict{
+    /// Cell in our document, in the merged one and in theirs, missing where the row was deleted,
+    /// the first cell of the rows for a row conflict, missing in ours or theirs when they have none
+    pub cells: [Option<Position>; 3],
+    /// Values of the cell, or the rows as delimited text for a row conflict, empty where deleted
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+    /// Number of rows in each document for a row conflict
+    pub rows: Option<[usize; 3]>,
+    /// What the merged cell or row starts with, the conflict being resolved once it changes
+    pub pending: String,
+    /// Depth in the history of the merged document of the edit picking the side the merged cell
+    /// or rows start with, the conflict staying resolved while that edit is not undone
+    pub kept: Option<usize>,
+    pub resolved: bool,
+}
+
+/// Conflict on a deleted row: its index, its column and its row from the first deleted
+type SetAside = (usize, usize, usize);
+
+/// Conflicts of a merge being resolved
+pub struct Merge{
+    pub conflicts: Vec<Conflict>,
+    /// Conflict last moved to
+    pub current: Option<usize>,
+    /// Whether the merged file was saved with every conflict resolved
+    pub saved: bool,
+    /// Rows deleted from the merged document, the last ones last, with the conflicts that were
+    /// on them, to put back when the deletion is undone
+    removed: Vec<(usize, Vec<SetAside>)>,
+}
+
+impl Merge{
+    #[must_use]
+    pub fn new(conflicts: Vec<Conflict>) -> Self{
+        Self{conflicts, current: None, saved: false, removed: Vec::new()}
+    }
+
+    /// Checks again which conflicts are resolved from the merged document, whose history is
+    /// `depth` edits deep, a conflict whose merged row was deleted being resolved
+    pub fn refresh(&mut self, merged: &Document, depth: usize){
+        for conflict in &mut self.conflicts{
+            let value = match (conflict.cells[MERGED], conflict.rows){
+                (None, _) => None,
+                (Some(p), Some(rows)) => Some(merged.rows.iter().skip(p.y).take(rows[MERGED]).map(|row| row.stringify(";")).collect::<Vec<String>>().join("\n")),
+                (Some(p), None) => Some(merged.get_cell(&p).map(|cell| cell.val.to_string()).unwrap_or_default()),
+            };
+            let kept = conflict.kept.is_some_and(|at| at <= depth);
+            conflict.resolved = value.is_none_or(|value| kept || value != conflict.pending);
+        }
+    }
+
+    /// Forgets the sides picked by the edits undone, which can no longer be redone once the merged
+    /// document is edited again, its history being `depth` edits deep before that edit
+    pub fn edited(&mut self, depth: usize){
+        for conflict in &mut self.conflicts{
+            conflict.kept = conflict.kept.filter(|at| *at <= depth);
+        }
+    }
+
+    /// Keeps the conflicts on their rows when the `removed` rows of the merged document from `at` were
+    /// replaced by `inserted` rows. The conflicts on rows deleted are set aside; with `restored`, the rows
+    /// are the last ones deleted coming back and the conflicts set aside with them are put back
+    pub fn rows_replaced(&mut self, at: usize, removed: usize, inserted: usize, restored: bool){
+        let end = at + removed;
+        let mut back = Vec::new();
+        if restored && inserted > 0 && self.removed.last().is_some_and(|(row, _)| *row == at){
+            back = self.removed.pop().map(|(_, conflicts)| conflicts).unwrap_or_default();
+        }
+
+        let mut set_aside = Vec::new();
+        for (i, conflict) in self.conflicts.iter_mut().enumerate(){
+            let Some(p) = conflict.cells[MERGED].as_mut() else{
+                continue;
+            };
+            if let Some(rows) = conflict.rows.as_mut(){
+                let (first, last) = (p.y, p.y + rows[MERGED]);
+                if at == first && removed == rows[MERGED]{
+                    // The rows of the conflict themselves
+                    rows[MERGED] = inserted;
+                }else if end <= first{
+                    p.y = first - removed + inserted;
+                }else if at < last{
+                    p.y = first.min(at);
+                    rows[MERGED] = last.max(end) - p.y - removed + inserted;
+                }
+            }else if p.y >= end{
+                p.y = p.y - removed + inserted;
+            }else if p.y >= at{
+                set_aside.push((i, p.x, p.y - at));
+                conflict.cells[MERGED] = None;
+            }
+        }
+        for (i, x, offset) in back.into_iter().filter(|(_, _, offset)| *offset < inserted){
+            self.conflicts[i].cells[MERGED] = Some(Position{x, y: at + offset});
+        }
+        if removed > 0{
+            self.removed.push((at, set_aside));
+        }
+    }
+
+    #[must_use]
+    pub fn unresolved(&self) -> usize{
+        self.conflicts.iter().filter(|conflict| !conflict.resolved).count()
+    }
+
+    /// Conflict at the cell `p` of the pane `pane`
+    #[must_use]
+    pub fn conflict_at(&self, pane: usize, p: &Position) -> Option<usize>{
+        self.conflicts.iter().position(|conflict| {
+            conflict.cells.get(pane).copied().flatten().is_some_and(|at| match conflict.rows{
+                Some(rows) => (at.y..at.y + rows[pane]).contains(&p.y),
+                None => at == *p,
+            })
+        })
+    }
+
+    /// Background of the cell `p` of the pane `pane`, when it is part of a conflict
+    #[must_use]
+    pub fn color(&self, pane: usize, p: &Position) -> Option<Color>{
+        let conflict = &self.conflicts[self.conflict_at(pane, p)?];
+        Some(if conflict.resolved { Color::DarkGreen } else { Color::Magenta })
+    }
+}
+
+/// Merges the changes made to `base` in `ours` and `theirs`, returning the merged rows and the conflicts
+#[must_use]
+pub fn merge(base: &Document, ours: &Document, theirs: &Document) -> (Vec<Row>, Vec<Conflict>){
+    let texts = |document: &Document| -> Vec<String> { document.rows.iter().map(|row| row.stringify(";")).collect() };
+    let (base_texts, our_texts, their_texts) = (texts(base), texts(ours), texts(theirs));
+    let with_ours = align(&base_texts, &our_texts);
+    let with_theirs = align(&base_texts, &their_texts);
+
+    let mut rows = Vec::new();
+    let mut conflicts = Vec::new();
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop{
+        // Next base row kept unchanged on both sides
+        let stable = (b..base.len).find(|i| with_ours[*i].is_some_and(|j| j >= o) && with_theirs[*i].is_some_and(|j| j >= t));
+        let (b_end, o_end, t_end) = match stable{
+            Some(i) => (i, with_ours[i].unwrap_or(o), with_theirs[i].unwrap_or(t)),
+            None => (base.len, ours.len, theirs.len),
+        };
+
+        let chunk = Chunk{base: (base, b..b_end), ours: (ours, o..o_end), theirs: (theirs, t..t_end)};
+        chunk.merge(&mut rows, &mut conflicts);
+
+        let Some(i) = stable else{
+            break;
+        };
+        rows.push(base.rows[i].clone());
+        (b, o, t) = (b_end + 1, o_end + 1, t_end + 1);
+    }
+    (rows, conflicts)
+}
+
+/// Rows of a document
+type Part<'a> = (&'a Document, Range<usize>);
+
+/// Rows changed between two stable rows, in each document
+struct Chunk<'a>{
+    base: Part<'a>,
+    ours: Part<'a>,
+    theirs: Part<'a>,
+}
+
+impl Chunk<'_>{
+    fn texts(part: &Part) -> Vec<String>{
+        part.0.rows[part.1.clone()].iter().map(|row| row.stringify(";")).collect()
+    }
+
+    fn merge(&self, rows: &mut Vec<Row>, conflicts: &mut Vec<Conflict>){
+        let (base, ours, theirs) = (Self::texts(&self.base), Self::texts(&self.ours), Self::texts(&self.theirs));
+        let take = |part: &Part, rows: &mut Vec<Row>| rows.extend(part.0.rows[part.1.clone()].iter().cloned());
+        if ours == base || ours == theirs{
+            take(&self.theirs, rows);
+            return;
+        }
+        if theirs == base{
+            take(&self.ours, rows);
+            return;
+        }
+
+        if base.len() != ours.len() || ours.len() != theirs.len(){
+            // Rows inserted or deleted on one side cannot be paired with the rows of the other
+            let kept = if self.ours.1.is_empty() { &self.theirs } else { &self.ours };
+            let first = |part: &Part| Some(Position{x: 0, y: part.1.start}).filter(|_| !part.1.is_empty());
+            conflicts.push(Conflict{
+                cells: [first(&self.ours), Some(Position{x: 0, y: rows.len()}), first(&self.theirs)],
+                base: base.join("\n"),
+                ours: ours.join("\n"),
+                theirs: theirs.join("\n"),
+                rows: Some([ours.len(), kept.1.len(), theirs.len()]),
+                pending: Self::texts(kept).join("\n"),
+                kept: None,
+                resolved: false,
+            });
+            take(kept, rows);
+            return;
+        }
+
+        // Both sides changed the same number of rows, they are paired by position
+        for k in 0..base.len(){
+            let (b, o, t) = (self.base.1.start + k, self.ours.1.start + k, self.theirs.1.start + k);
+            let row = if ours[k] == base[k] || ours[k] == theirs[k]{
+                self.theirs.0.rows[t].clone()
+            }else if theirs[k] == base[k]{
+                self.ours.0.rows[o].clone()
+            }else{
+                self.merge_cells([o, rows.len(), t], b, conflicts)
+            };
+            rows.push(row);
+        }
+    }
+
+    /// Merges the rows `o` and `t` cell by cell against the base row `b`, `at[MERGED]` being the merged row
+    fn merge_cells(&self, at: [usize; 3], b: usize, conflicts: &mut Vec<Conflict>) -> Row{
+        let [o, y, t] = at;
+        let cell = |document: &Document, y: usize, x: usize| -> Option<Cell>{
+            document.get_cell(&Position{x, y}).cloned()
+        };
+        let value = |cell: &Option<Cell>| cell.as_ref().map(|cell| cell.val.to_string()).unwrap_or_default();
+        let columns = [(self.ours.0, o), (self.theirs.0, t), (self.base.0, b)].iter()
+            .map(|(document, y)| document.rows[*y].cells.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut cells = Vec::new();
+        for x in 0..columns{
+            let (base_cell, our_cell, their_cell) = (cell(self.base.0, b, x), cell(self.ours.0, o, x), cell(self.theirs.0, t, x));
+            let (base_value, our_value, their_value) = (value(&base_cell), value(&our_cell), value(&their_cell));
+            let merged = if our_value == base_value || our_value == their_value{
+                their_cell
+            }else if their_value == base_value{
+                our_cell
+            }else{
+                let position = |y: usize| Some(Position{x, y});
+                conflicts.push(Conflict{
+                    cells: [position(o), position(y), position(t)],
+                    base: base_value,
+                    pending: our_value.clone(),
+                    ours: our_value,
+                    theirs: their_value,
+                    rows: None,
+                    kept: None,
+                    resolved: false,
+                });
+                our_cell
+            };
+            cells.push(merged.unwrap_or_default());
+        }
+        Row{len: cells.len(), cells}
+    }
+}
+
+/// Row of `new` each row of `old` is kept as, from their longest common subsequence
+fn align(old: &[String], new: &[String]) -> Vec<Option<usize>>{
+    let mut result = vec![None; old.len()];
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    for (i, slot) in result.iter_mut().enumerate().take(prefix){
+        *slot = Some(i);
+    }
+    for i in 0..suffix{
+        result[old.len() - 1 - i] = Some(new.len() - 1 - i);
+    }
+
+    let (old_mid, new_mid) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    if old_mid.len().saturating_mul(new_mid.len()) > MAX_ALIGNED{
+        for (i, slot) in result.iter_mut().skip(prefix).take(old_mid.len().min(new_mid.len())).enumerate(){
+            if old_mid[i] == new_mid[i]{
+                *slot = Some(prefix + i);
+            }
+        }
+        return result;
+    }
+
+    // lengths[i][j]: longest common subsequence of old_mid[i..] and new_mid[j..]
+    let (n, m) = (old_mid.len(), new_mid.len());
+    let mut lengths = vec![vec![0_usize; m + 1]; n + 1];
+    for i in (0..n).rev(){
+        for j in (0..m).rev(){
+            lengths[i][j] = if old_mid[i] == new_mid[j] { lengths[i + 1][j + 1] + 1 } else { lengths[i + 1][j].max(lengths[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m{
+        if old_mid[i] == new_mid[j]{
+            result[prefix + i] = Some(prefix + j);
+            i += 1;
+            j += 1;
+        }else if lengths[i + 1][j] >= lengths[i][j + 1]{
+            i += 1;
+        }else{
+            j += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::history::Ranges;
+
+    fn document(text: &str) -> Document{
+        let mut document = Document::default();
+        document.replace_rows(text.lines().map(|line| Row::parse(line, ';')).collect());
+        document
+    }
+
+    fn lines(text: &str) -> Vec<String>{
+        text.lines().map(str::to_string).collect()
+    }
+
+    fn merged(base: &str, ours: &str, theirs: &str) -> (Vec<String>, Vec<Conflict>){
+        let (rows, conflicts) = merge(&document(base), &document(ours), &document(theirs));
+        (rows.iter().map(|row| row.stringify(";")).collect(), conflicts)
+    }
+
+    #[test]
+    fn align_pairs_the_rows_kept(){
+        assert_eq!(align(&lines("a\nb\nc"), &lines("a\nb\nc")), [Some(0), Some(1), Some(2)]);
+        assert_eq!(align(&lines("a\nb\nc"), &lines("a\nx\nc")), [Some(0), None, Some(2)]);
+        assert_eq!(align(&lines("a\nb\nc"), &lines("a\nc")), [Some(0), None, Some(1)]);
+        assert_eq!(align(&lines("a\nb\nc"), &lines("x\na\ny\nb\nc\nz")), [Some(1), Some(3), Some(4)]);
+        assert_eq!(align(&lines("a\nb"), &[]), [None, None]);
+    }
+
+    #[test]
+    fn merge_takes_the_changes_of_both_sides(){
+        let (rows, conflicts) = merged("a;1\nb;2\nc;3", "a;10\nb;2\nc;3", "a;1\nb;2\nc;30\nd;4");
+        assert_eq!(rows, ["a;10", "b;2", "c;30", "d;4"]);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn merge_combines_the_cells_of_a_row_changed_on_both_sides(){
+        let (rows, conflicts) = merged("a;1;x", "b;1;x", "a;1;y");
+        assert_eq!(rows, ["b;1;y"]);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn merge_keeps_ours_for_a_cell_changed_differently(){
+        let (rows, conflicts) = merged("k;a;1\nz;b;2", "k;a;10\nz;b;2", "k;a;20\nz;b;2");
+        assert_eq!(rows, ["k;a;10", "z;b;2"]);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert!(conflict.rows.is_none() && !conflict.resolved);
+        assert_eq!((conflict.base.as_str(), conflict.ours.as_str(), conflict.theirs.as_str()), ("1", "10", "20"));
+        assert_eq!(conflict.cells.map(|p| p.map(|p| (p.x, p.y))), [Some((2, 0)), Some((2, 0)), Some((2, 0))]);
+    }
+
+    #[test]
+    fn merge_reports_a_row_deleted_on_one_side_and_changed_on_the_other(){
+        let (rows, conflicts) = merged("a;1\nb;2\nc;3", "a;1\nc;3", "a;1\nb;20\nc;3");
+        assert_eq!(rows, ["a;1", "b;20", "c;3"]);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.rows, Some([0, 1, 1]));
+        assert!(!conflict.resolved);
+        assert_eq!((conflict.ours.as_str(), conflict.theirs.as_str(), conflict.pending.as_str()), ("", "b;20", "b;20"));
+        assert_eq!(conflict.cells.map(|p| p.map(|p| (p.x, p.y))), [None, Some((0, 1)), Some((0, 1))]);
+    }
+
+    #[test]
+    fn merge_reports_rows_inserted_on_one_side_and_changed_on_the_other(){
+        let (rows, conflicts) = merged("a;1", "z;9\na;1", "a;2");
+        assert_eq!(rows, ["z;9", "a;1"]);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.rows, Some([2, 2, 1]));
+        assert_eq!((conflict.base.as_str(), conflict.ours.as_str(), conflict.theirs.as_str()), ("a;1", "z;9\na;1", "a;2"));
+        assert_eq!(conflict.cells.map(|p| p.map(|p| (p.x, p.y))), [Some((0, 0)), Some((0, 0)), Some((0, 0))]);
+    }
+
+    #[test]
+    fn merge_reports_rows_deleted_on_one_side_and_changed_on_the_other_in_the_same_chunk(){
+        let (rows, conflicts) = merged("k\na;1\nb;2\nz", "k\na;10\nz", "k\na;1\nb;20\nz");
+        assert_eq!(rows, ["k", "a;10", "z"]);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.rows, Some([1, 1, 2]));
+        assert_eq!((conflict.ours.as_str(), conflict.theirs.as_str(), conflict.pending.as_str()), ("a;10", "a;1\nb;20", "a;10"));
+        assert_eq!(conflict.cells.map(|p| p.map(|p| (p.x, p.y))), [Some((0, 1)), Some((0, 1)), Some((0, 1))]);
+        let merge = Merge::new(conflicts);
+        assert_eq!(merge.conflict_at(THEIRS, &Position{x: 3, y: 2}), Some(0));
+        assert_eq!(merge.conflict_at(MERGED, &Position{x: 0, y: 2}), None);
+    }
+
+    #[test]
+    fn merge_deletes_a_row_deleted_on_one_side_and_unchanged_or_deleted_on_the_other(){
+        for theirs in ["a\nb\nc", "a\nc"]{
+            let (rows, conflicts) = merged("a\nb\nc", "a\nc", theirs);
+            assert_eq!(rows, ["a", "c"]);
+            assert!(conflicts.is_empty());
+        }
+    }
+
+    #[test]
+    fn refresh_follows_the_merged_document(){
+        let (rows, conflicts) = merge(&document("a;1"), &document("a;10"), &document("a;20"));
+        let mut merge = Merge::new(conflicts);
+        let mut merged = Document::default();
+        merged.replace_rows(rows);
+
+        merged.insert_cell(&Position{x: 1, y: 0}, &Cell::from("20".to_string()));
+        merge.refresh(&merged, 1);
+        assert!(merge.conflicts[0].resolved);
+
+        // Undone
+        merged.insert_cell(&Position{x: 1, y: 0}, &Cell::from("10".to_string()));
+        merge.refresh(&merged, 0);
+        assert!(!merge.conflicts[0].resolved);
+
+        // Ours picked
+        merge.conflicts[0].kept = Some(1);
+        merge.refresh(&merged, 1);
+        assert!(merge.conflicts[0].resolved);
+
+        // The pick undone, then redone
+        merge.refresh(&merged, 0);
+        assert!(!merge.conflicts[0].resolved);
+        merge.refresh(&merged, 1);
+        assert!(merge.conflicts[0].resolved);
+
+        // The pick undone, then another edit made
+        merge.edited(0);
+        merge.refresh(&merged, 1);
+        assert!(!merge.conflicts[0].resolved);
+    }
+
+    #[test]
+    fn conflicts_follow_the_rows_of_the_merged_document(){
+        let (rows, conflicts) = merged("a;1\nb;2\nc;3", "a;10\nb;2\nc;30", "a;11\nb;2\nc;31");
+        assert_eq!(rows.len(), 3);
+        let mut merge = Merge::new(conflicts);
+        let merged_row = |merge: &Merge| merge.conflicts.iter().map(|c| c.cells[MERGED].map(|p| p.y)).collect::<Vec<Option<usize>>>();
+
+        merge.rows_replaced(1, 0, 1, false);
+        assert_eq!(merged_row(&merge), [Some(0), Some(3)]);
+        merge.rows_replaced(0, 1, 0, false);
+        assert_eq!(merged_row(&merge), [None, Some(2)]);
+        assert_eq!(merge.conflict_at(MERGED, &Position{x: 1, y: 2}), Some(1));
+
+        // The deletion undone
+        merge.rows_replaced(0, 0, 1, true);
+        assert_eq!(merged_row(&merge), [Some(0), Some(3)]);
+        assert_eq!(merge.conflicts[0].cells[MERGED].map(|p| p.x), Some(1));
+    }
+
+    #[test]
+    fn row_conflicts_follow_the_rows_replacing_theirs(){
+        let (rows, conflicts) = merged("k\na;1\nz", "k\nz", "k\na;2\nz");
+        assert_eq!(rows, ["k", "a;2", "z"]);
+        let mut merge = Merge::new(conflicts);
+        let mut merged = Document::default();
+        merged.replace_rows(rows.iter().map(|row| Row::parse(row, ';')).collect());
+
+        // Ours picked, deleting the row
+        merged.del_row(1);
+        merge.rows_replaced(1, 1, 0, false);
+        merge.refresh(&merged, 1);
+        assert_eq!(merge.conflicts[0].rows, Some([0, 0, 1]));
+        assert!(merge.conflicts[0].resolved);
+
+        // Undone
+        merged.restore_row(1, Row::parse("a;2", ';'), Ranges::default());
+        merge.rows_replaced(1, 0, 1, true);
+        merge.refresh(&merged, 0);
+        assert_eq!(merge.conflicts[0].rows, Some([0, 1, 1]));
+        assert!(!merge.conflicts[0].resolved);
+
+        // A row inserted above
+        merge.rows_replaced(0, 0, 1, false);
+        assert_eq!(merge.conflicts[0].cells[MERGED].map(|p| p.y), Some(2));
+    }
+}
@@ -0,0 +1,56 @@
+//! Split panes: the screen below the edit line is shared by the open files,
+//! stacked one above the other or side by side.
+
+use crate::terminal::Size;
+
+/// Lines above the panes: status line and edit line
+pub const TOP: usize = 2;
+/// Lines of a pane above its rows: sheet tabs and column headers
+pub const HEADER_LINES: usize = 2;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Split{
+    /// Panes stacked one above the other
+    Horizontal,
+    /// Panes side by side, a separator column between them
+    Vertical,
+}
+
+/// Part of the screen given to a pane: its sheet tabs on the first line,
+/// then its column headers and `rows` lines of cells
+#[derive(Clone, Copy)]
+pub struct Area{
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub rows: usize,
+}
+
+impl Area{
+    /// Area of the pane `index` out of `count`, `size` being the room a single grid would have
+    #[must_use]
+    pub fn of(index: usize, count: usize, split: Split, size: Size) -> Self{
+        let count = count.max(1);
+        match split{
+            Split::Horizontal => {
+                let lines = size.height + HEADER_LINES;
+                let share = lines / count;
+                let top = TOP + index * share;
+                // The last pane takes the lines left by the rounding
+                let height = if index + 1 == count { lines - index * share } else { share };
+                Self{left: 0, top, width: size.width, rows: height.saturating_sub(HEADER_LINES)}
+            },
+            Split::Vertical => {
+                let share = size.width.saturating_sub(count - 1) / count;
+                let left = index * (share + 1);
+                let width = if index + 1 == count { size.width.saturating_sub(left) } else { share };
+                Self{left, top: TOP, width, rows: size.height}
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, column: usize, line: usize) -> bool{
+        (self.left..self.left + self.width).contains(&column) && (self.top..self.top + HEADER_LINES + self.rows).contains(&line)
+    }
+}
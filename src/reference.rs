@@ -0,0 +1,163 @@
+use crate::Position;
+
+const ALPHABET: &[u8; 26] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Column name of a zero based index, using bijective base-26 (A..Z, AA, AB, ...)
+#[must_use]
+pub fn column_name(index: usize) -> String{
+    let mut name = Vec::new();
+    let mut n = index.saturating_add(1);
+    while n > 0{
+        let rem = (n - 1) % 26;
+        name.push(char::from(ALPHABET[rem]));
+        n = (n - 1) / 26;
+    }
+    name.iter().rev().collect()
+}
+
+/// Zero based index of a column name, case insensitive
+#[must_use]
+pub fn column_index(name: &str) -> Option<usize>{
+    if name.is_empty(){
+        return None;
+    }
+
+    let mut n: usize = 0;
+    for c in name.chars(){
+        if !c.is_ascii_alphabetic(){
+            return None;
+        }
+        let digit = (c.to_ascii_uppercase() as usize) - ('A' as usize) + 1;
+        n = n.checked_mul(26)?.checked_add(digit)?;
+    }
+    Some(n - 1)
+}
+
+/// Rectangular block of cells, both corners included
+#[derive(PartialEq, Clone, Copy)]
+pub struct Range{
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range{
+    #[must_use]
+    pub fn new(a: Position, b: Position) -> Self{
+        Self{
+            start: Position{x: a.x.min(b.x), y: a.y.min(b.y)},
+            end: Position{x: a.x.max(b.x), y: a.y.max(b.y)},
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, p: &Position) -> bool{
+        (self.start.x..=self.end.x).contains(&p.x) && (self.start.y..=self.end.y).contains(&p.y)
+    }
+
+    /// Range once a column is inserted at `at`, growing when inserted inside it
+    #[must_use]
+    pub fn column_inserted(self, at: usize) -> Self{
+        let shift = |x: usize| if x >= at { x + 1 } else { x };
+        Self{start: Position{x: shift(self.start.x), y: self.start.y}, end: Position{x: shift(self.end.x), y: self.end.y}}
+    }
+
+    /// Range once the column `at` is deleted, `None` when it was its only column
+    #[must_use]
+    pub fn column_deleted(self, at: usize) -> Option<Self>{
+        if self.start.x == at && self.end.x == at{
+            return None;
+        }
+        let shift = |x: usize| if x > at { x - 1 } else { x };
+        Some(Self{start: Position{x: shift(self.start.x), y: self.start.y}, end: Position{x: shift(self.end.x), y: self.end.y}})
+    }
+
+    /// Range once a row is inserted at `at`, growing when inserted inside it
+    #[must_use]
+    pub fn row_inserted(self, at: usize) -> Self{
+        let shift = |y: usize| if y >= at { y + 1 } else { y };
+        Self{start: Position{x: self.start.x, y: shift(self.start.y)}, end: Position{x: self.end.x, y: shift(self.end.y)}}
+    }
+
+    /// Range once the row `at` is deleted, `None` when it was its only row
+    #[must_use]
+    pub fn row_deleted(self, at: usize) -> Option<Self>{
+        if self.start.y == at && self.end.y == at{
+            return None;
+        }
+        let shift = |y: usize| if y > at { y - 1 } else { y };
+        Some(Self{start: Position{x: self.start.x, y: shift(self.start.y)}, end: Position{x: self.end.x, y: shift(self.end.y)}})
+    }
+}
+
+/// A1 name of a cell, rows being numbered from 1
+#[must_use]
+pub fn cell_name(p: &Position) -> String{
+    format!("{}{}", column_name(p.x), p.y.saturating_add(1))
+}
+
+/// Parses a cell in A1 (`B17`) or R1C1 (`R17C2`) notation
+#[must_use]
+pub fn parse_cell(s: &str) -> Option<Position>{
+    let s = s.trim().to_ascii_uppercase();
+    if let Some((row, col)) = s.strip_prefix('R').and_then(|rest| rest.split_once('C')){
+        if let (Ok(row), Ok(col)) = (row.parse::<usize>(), col.parse::<usize>()){
+            return Some(Position{x: col.checked_sub(1)?, y: row.checked_sub(1)?});
+        }
+    }
+
+    let split = s.find(|c: char| c.is_ascii_digit())?;
+    let x = column_index(&s[..split])?;
+    let y = s[split..].parse::<usize>().ok()?.checked_sub(1)?;
+    Some(Position{x, y})
+}
+
+/// Splits the sheet off a reference such as `Sheet2!B4` or `'Q1 sales'!B4`
+#[must_use]
+pub fn split_sheet(s: &str) -> (Option<&str>, &str){
+    match s.rsplit_once('!'){
+        Some((sheet, rest)) => (Some(sheet.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')).unwrap_or(sheet)), rest),
+        None => (None, s),
+    }
+}
+
+/// Parses a range such as `B2:D10`, or a single cell
+#[must_use]
+pub fn parse_range(s: &str) -> Option<Range>{
+    match s.split_once(':'){
+        Some((start, end)) => Some(Range::new(parse_cell(start)?, parse_cell(end)?)),
+        None => parse_cell(s).map(|p| Range::new(p, p)),
+    }
+}
+
+impl std::fmt::Display for Range{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        write!(f, "{}:{}", cell_name(&self.start), cell_name(&self.end))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn range(s: &str) -> Range{
+        parse_range(s).unwrap()
+    }
+
+    #[test]
+    fn ranges_follow_inserted_rows_and_columns(){
+        assert_eq!(range("B2:C4").row_inserted(0).to_string(), "B3:C5");
+        assert_eq!(range("B2:C4").row_inserted(2).to_string(), "B2:C5");
+        assert_eq!(range("B2:C4").row_inserted(4).to_string(), "B2:C4");
+        assert_eq!(range("B2:C4").column_inserted(1).to_string(), "C2:D4");
+        assert_eq!(range("B2:C4").column_inserted(2).to_string(), "B2:D4");
+    }
+
+    #[test]
+    fn ranges_shrink_with_deleted_rows_and_columns(){
+        assert_eq!(range("B2:C4").row_deleted(0).map(|r| r.to_string()).as_deref(), Some("B1:C3"));
+        assert_eq!(range("B2:C4").row_deleted(2).map(|r| r.to_string()).as_deref(), Some("B2:C3"));
+        assert_eq!(range("B2:C4").column_deleted(1).map(|r| r.to_string()).as_deref(), Some("B2:B4"));
+        assert!(range("B2:C2").row_deleted(1).is_none());
+        assert!(range("B2:B4").column_deleted(1).is_none());
+    }
+}
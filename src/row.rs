@@ -1,39 +1,41 @@
 use crate::{Cell, SearchDirection};
 
+#[derive(Default, Clone)]
 pub struct Row{
     pub cells: Vec<Cell>,
     pub len: usize,
 }
 
-impl Default for Row{
-    fn default() -> Self {
-        Self{
-            cells: Vec::new(),
-            len: 0
-        }
-    }
-}
-
 impl Row{
+    #[must_use]
     pub fn render(&self) -> String{
         let mut result = String::new();
         for i in &self.cells{
-            result.push_str(&format!("{} | ", i.val));
+            result.push_str(&i.val.to_string());
+            result.push_str(" | ");
         }
 
         result
     }
 
+    /// Row of the cells of a line delimited by `sep`
+    #[must_use]
+    pub fn parse(line: &str, sep: char) -> Self{
+        let cells: Vec<Cell> = line.split(sep).map(|s| Cell::from(s.to_string())).collect();
+        Self{len: cells.len(), cells}
+    }
+
+    #[must_use]
     pub fn stringify(&self, sep: &str) -> String{
-        self.cells.iter().map(|cell| cell.render(0)).collect::<Vec<String>>().join(sep)
+        self.cells.iter().map(|cell| cell.val.to_string()).collect::<Vec<String>>().join(sep)
     }
 
     pub fn insert(&mut self, c: char, at: usize){
         if self.len <= at{
-            self.fill(at.saturating_sub(self.len).saturating_add(1))
+            self.fill(at.saturating_sub(self.len).saturating_add(1));
         }
 
-        self.cells[at].insert(c)
+        self.cells[at].insert(c);
     }
 
     pub fn insert_cell(&mut self, at: usize, cell: &Cell){
@@ -43,6 +45,37 @@ impl Row{
         self.cells[at] = cell.clone();
     }
 
+    /// Shifts the cells from `at` one column to the right, leaving an empty cell at `at`,
+    /// which may be just past the last cell
+    pub fn insert_column(&mut self, at: usize) -> bool{
+        if self.len < at{
+            return false;
+        }
+
+        self.cells.insert(at, Cell::default());
+        self.update_len();
+        true
+    }
+
+    pub fn remove_column(&mut self, at: usize) -> Option<Cell>{
+        if self.len <= at{
+            return None;
+        }
+
+        let cell = self.cells.remove(at);
+        self.update_len();
+        Some(cell)
+    }
+
+    pub fn restore_column(&mut self, at: usize, cell: Cell){
+        if self.len < at{
+            self.fill(at.saturating_sub(self.len));
+        }
+
+        self.cells.insert(at, cell);
+        self.update_len();
+    }
+
     pub fn delete(&mut self, at: usize){
         if self.cells.len() <= at{
             return;
@@ -50,50 +83,34 @@ impl Row{
 
         let len = self.cells[at].val.len();
 
-        self.cells[at].delete(len)
+        self.cells[at].delete(len);
     }
 
+    /// Index of the first cell containing `query`, starting at `at` included
+    /// and going toward the end or the start of the row
+    #[must_use]
     pub fn find(&self, query: &str, at:usize, direction: SearchDirection) -> Option<usize>{
-        if at > self.len || query.is_empty(){
+        if self.len == 0 || query.is_empty(){
             return None;
         }
-        let start = if direction == SearchDirection::Forward {
-            at
-        } else {
-            0
-        };
-        let end = if direction == SearchDirection::Forward {
-            self.len
-        } else {
-            at
-        };
 
+        let contains = |i: &usize| self.cells[*i].val.to_string().contains(query);
         if direction == SearchDirection::Forward{
-            for i in start..end{
-                if self.cells[i].val.to_string().contains(query){
-                    return Some(i)
-                }
-            }
+            (at..self.len).find(contains)
         }else{
-            for i in (start..end).rev(){
-                if self.cells[i].val.to_string().contains(query){
-                    return Some(i)
-                }
-            }
+            (0..=at.min(self.len - 1)).rev().find(contains)
         }
-
-        None
     }
 
     pub fn fill(&mut self, n: usize){
         for _ in 0..n{
-            self.cells.push(Cell::default())
+            self.cells.push(Cell::default());
         }
 
-        self.update_len()
+        self.update_len();
     }
 
     fn update_len(&mut self){
-        self.len = self.cells.len()
+        self.len = self.cells.len();
     }
 }
\ No newline at end of file
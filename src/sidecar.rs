@@ -0,0 +1,94 @@
+//! Layout kept next to a delimited file, in `<file>.rexcel`,
+//! since the file itself can only store values.
+//!
+//! One setting per line, e.g. `width B 14`, `format C #,##0.00`, `name totals B2:D10`,
+//! `style B3 bold fg=red`, `rule C2:C40 <0 -> fg=red`, `pattern %d/%m/%Y` or `sheet Totals`.
+
+use std::fs;
+use std::io::ErrorKind;
+
+use crate::atomic;
+use crate::reference::{cell_name, column_index, column_name, parse_cell, parse_range};
+use crate::Position;
+use crate::Document;
+use crate::conditional::Rule;
+use crate::style::NumberFormat;
+
+#[must_use]
+pub fn path(filename: &str) -> String{
+    format!("{filename}.rexcel")
+}
+
+/// Applies the settings found in the sidecar of `filename`, if any
+pub fn load(filename: &str, document: &mut Document){
+    let Ok(content) = fs::read_to_string(path(filename)) else{
+        return;
+    };
+
+    for line in content.lines(){
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice(){
+            ["width", column, width] => {
+                if let (Some(x), Ok(width)) = (column_index(column), width.parse::<usize>()){
+                    document.set_col_width(x, width);
+                }
+            },
+            ["format", column, format] => {
+                if let (Some(x), Some(format)) = (column_index(column), NumberFormat::parse(format)){
+                    document.set_col_format(x, Some(format));
+                }
+            },
+            ["name", name, range] => {
+                if let Some(range) = parse_range(range){
+                    document.define_name(name, range);
+                }
+            },
+            // Rows removed since by another program don't come back to hold a style
+            ["style", cell, spec @ ..] => {
+                if let Some(p) = parse_cell(cell).filter(|p| p.y < document.len){
+                    let mut styled = document.get_cell(&p).cloned().unwrap_or_default();
+                    if styled.style.apply(&spec.join(" ")).is_ok(){
+                        document.insert_cell(&p, &styled);
+                    }
+                }
+            },
+            ["sheet", name] => document.sheet_names.push((*name).to_string()),
+            ["pattern", pattern @ ..] if !pattern.is_empty() => document.patterns.push(pattern.join(" ")),
+            ["rule", range, spec @ ..] => {
+                if let Some(Ok(rule)) = parse_range(range).map(|range| Rule::parse(range, &spec.join(" "))){
+                    document.rules.push(rule);
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Writes the settings of `document` to the sidecar of `filename`,
+/// removing it when there is nothing left to keep
+///
+/// # Errors
+/// Will return an error when the sidecar can't be written or removed
+pub fn save(filename: &str, document: &Document) -> Result<(), std::io::Error>{
+    let lines: Vec<String> = document.sheet_names.iter().map(|name| format!("sheet {name}"))
+        .chain(document.custom_widths()
+            .map(|(x, width)| format!("width {} {}", column_name(x), width)))
+        .chain(document.custom_formats().map(|(x, format)| format!("format {} {}", column_name(x), format.spec())))
+        .chain(document.names.iter().map(|(name, range)| format!("name {name} {range}")))
+        .chain(document.rows.iter().enumerate().flat_map(|(y, row)| {
+            row.cells.iter().enumerate()
+                .filter(|(_, cell)| !cell.style.is_default())
+                .map(move |(x, cell)| format!("style {} {}", cell_name(&Position{x, y}), cell.style.spec()))
+        }))
+        .chain(document.rules.iter().map(|rule| format!("rule {} {}", rule.range, rule.spec)))
+        .chain(document.patterns.iter().map(|pattern| format!("pattern {pattern}")))
+        .collect();
+
+    if lines.is_empty(){
+        return match fs::remove_file(path(filename)){
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    atomic::write(&path(filename), false, |file| file.write_all((lines.join("\n") + "\n").as_bytes()))
+}
@@ -0,0 +1,228 @@
+use std::cmp::Ordering;
+
+use crate::cell::DataType;
+use crate::reference::column_index;
+use crate::Row;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum SortOrder{
+    Ascending,
+    Descending,
+}
+
+/// How two values of a column are compared
+#[derive(PartialEq, Clone, Copy)]
+pub enum SortMode{
+    /// Numbers numerically, before text compared lexicographically
+    Auto,
+    /// Everything compared as text, byte by byte
+    Text,
+    /// Text with embedded numbers compared by value (`file2` < `file10`)
+    Natural,
+}
+
+#[derive(Clone, Copy)]
+pub struct SortKey{
+    pub column: usize,
+    pub order: SortOrder,
+    pub mode: SortMode,
+}
+
+impl SortKey{
+    #[must_use]
+    pub fn new(column: usize, order: SortOrder) -> Self{
+        Self{
+            column,
+            order,
+            mode: SortMode::Auto,
+        }
+    }
+
+    /// Parses a comma separated spec such as `B desc, A natural`
+    ///
+    /// # Errors
+    /// Will return the offending part of the spec when it can't be parsed
+    pub fn parse_spec(spec: &str) -> Result<Vec<Self>, String>{
+        let mut keys = Vec::new();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()){
+            let mut words = part.split_whitespace();
+            let column = words.next().and_then(column_index).ok_or_else(|| part.to_string())?;
+            let mut key = Self::new(column, SortOrder::Ascending);
+            for word in words{
+                match word.to_lowercase().as_str(){
+                    "asc" => key.order = SortOrder::Ascending,
+                    "desc" => key.order = SortOrder::Descending,
+                    "auto" => key.mode = SortMode::Auto,
+                    "text" => key.mode = SortMode::Text,
+                    "natural" => key.mode = SortMode::Natural,
+                    _ => return Err(part.to_string()),
+                }
+            }
+            keys.push(key);
+        }
+
+        if keys.is_empty(){
+            return Err(spec.to_string());
+        }
+        Ok(keys)
+    }
+
+    fn compare(&self, a: &Row, b: &Row) -> Ordering{
+        let a = a.cells.get(self.column).map(|c| &c.val);
+        let b = b.cells.get(self.column).map(|c| &c.val);
+
+        // Blanks always go last, whatever the order
+        match (is_blank(a), is_blank(b)){
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Greater,
+            (false, true) => return Ordering::Less,
+            (false, false) => (),
+        }
+        let (a, b) = (a.unwrap_or(&DataType::Empty), b.unwrap_or(&DataType::Empty));
+
+        let ordering = match self.mode{
+            SortMode::Auto => compare_auto(a, b),
+            SortMode::Text => a.to_string().cmp(&b.to_string()),
+            SortMode::Natural => compare_natural(&a.to_string(), &b.to_string()),
+        };
+
+        match self.order{
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
+}
+
+/// Returns the stable permutation sorting `rows` by `keys`:
+/// the row at index `i` once sorted is `rows[permutation[i]]`
+#[must_use]
+pub fn permutation(rows: &[Row], keys: &[SortKey]) -> Vec<usize>{
+    let mut indexes: Vec<usize> = (0..rows.len()).collect();
+    indexes.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| key.compare(&rows[*a], &rows[*b]))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    indexes
+}
+
+fn is_blank(val: Option<&DataType>) -> bool{
+    match val{
+        None | Some(DataType::Empty) => true,
+        Some(DataType::String(s)) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// Dates and durations first, chronologically, then numbers, then text
+fn compare_auto(a: &DataType, b: &DataType) -> Ordering{
+    match (a.as_seconds(), b.as_seconds()){
+        (Some(x), Some(y)) => return x.cmp(&y),
+        (Some(_), None) => return Ordering::Less,
+        (None, Some(_)) => return Ordering::Greater,
+        (None, None) => (),
+    }
+
+    match (a.as_number(), b.as_number()){
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+fn compare_natural(a: &str, b: &str) -> Ordering{
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop{
+        match (a.peek().copied(), b.peek().copied()){
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_digits(&mut a);
+                let y = take_digits(&mut b);
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if ordering != Ordering::Equal{
+                    return ordering;
+                }
+            },
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal{
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+/// Consumes a run of digits, without its leading zeros
+fn take_digits<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> String{
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().copied(){
+        if !c.is_ascii_digit(){
+            break;
+        }
+        if !(digits.is_empty() && c == '0'){
+            digits.push(c);
+        }
+        chars.next();
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::temporal;
+
+    fn sorted(column: &[&str], spec: &str) -> Vec<String>{
+        let rows: Vec<Row> = column.iter().map(|val| Row::parse(val, ';')).collect();
+        let keys = SortKey::parse_spec(spec).unwrap();
+        permutation(&rows, &keys).into_iter().map(|i| column[i].to_string()).collect()
+    }
+
+    #[test]
+    fn specs_are_parsed(){
+        let keys = SortKey::parse_spec("B desc, A natural").unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys[0].column == 1 && keys[0].order == SortOrder::Descending && keys[0].mode == SortMode::Auto);
+        assert!(keys[1].column == 0 && keys[1].order == SortOrder::Ascending && keys[1].mode == SortMode::Natural);
+        assert_eq!(SortKey::parse_spec("A sideways").err().as_deref(), Some("A sideways"));
+        assert!(SortKey::parse_spec(" , ").is_err());
+    }
+
+    #[test]
+    fn auto_puts_numbers_before_text_and_blanks_last(){
+        assert_eq!(sorted(&["b", "10", "", "9", "a", "-1.5"], "A"), ["-1.5", "9", "10", "a", "b", ""]);
+        assert_eq!(sorted(&["b", "10", "", "9", "a"], "A desc"), ["b", "a", "10", "9", ""]);
+        assert_eq!(sorted(&["b", "10", "9", "a"], "A text"), ["10", "9", "a", "b"]);
+    }
+
+    #[test]
+    fn auto_orders_dates_chronologically(){
+        let rows: Vec<Row> = ["05/01/2024", "20/12/2023", "01/02/2024"].iter().map(|val| {
+            let mut row = Row::parse(val, ';');
+            row.cells[0].val = temporal::parse(val, &["%d/%m/%Y".to_string()]).unwrap();
+            row
+        }).collect();
+        assert_eq!(permutation(&rows, &SortKey::parse_spec("A").unwrap()), [1, 0, 2]);
+    }
+
+    #[test]
+    fn natural_compares_embedded_numbers_by_value(){
+        assert_eq!(sorted(&["file10", "File2", "file1", "file02b", "file"], "A natural"), ["file", "file1", "File2", "file02b", "file10"]);
+        assert_eq!(sorted(&["file10", "file2"], "A text"), ["file10", "file2"]);
+    }
+
+    #[test]
+    fn later_keys_break_ties_and_equal_rows_keep_their_order(){
+        let rows: Vec<Row> = ["x;2", "y;1", "x;1", "y;1"].iter().map(|line| Row::parse(line, ';')).collect();
+        assert_eq!(permutation(&rows, &SortKey::parse_spec("A, B").unwrap()), [2, 0, 1, 3]);
+        assert_eq!(permutation(&rows, &SortKey::parse_spec("B").unwrap()), [1, 2, 3, 0]);
+    }
+}
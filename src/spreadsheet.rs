@@ -0,0 +1,165 @@
+//! Spreadsheet files: every sheet of XLSX, XLS, XLSB and ODS files can be read,
+//! XLSX files can be written. Values and cell styles are kept, the rest of the layout stays
+//! in a sidecar per sheet.
+
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use calamine::{open_workbook_auto, Data, Reader};
+use chrono::NaiveTime;
+use rust_xlsxwriter::{Color, Format, FormatAlign, FormatUnderline, Workbook};
+
+use crate::atomic;
+use crate::cell::DataType;
+use crate::style::{self, Align, NumberFormat, Style};
+use crate::temporal::{self, Duration, DurationStyle};
+use crate::Cell;
+use crate::Document;
+use crate::Position;
+
+const READABLE: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+fn extension(filename: &str) -> String{
+    Path::new(filename).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+#[must_use]
+pub fn is_spreadsheet(filename: &str) -> bool{
+    READABLE.contains(&extension(filename).as_str())
+}
+
+/// Whether `filename` can be written back, only XLSX being supported
+#[must_use]
+pub fn is_writable(filename: &str) -> bool{
+    extension(filename) == "xlsx"
+}
+
+/// Reads every sheet of `filename`, with its name
+///
+/// # Errors
+/// Will return an error when the file can't be read or isn't a spreadsheet
+pub fn read(filename: &str) -> Result<Vec<(String, Document)>, Error>{
+    let invalid = |e: calamine::Error| Error::new(ErrorKind::InvalidData, e.to_string());
+    let mut workbook = open_workbook_auto(filename).map_err(invalid)?;
+
+    let mut sheets = Vec::new();
+    for name in workbook.sheet_names(){
+        let range = workbook.worksheet_range(&name).map_err(invalid)?;
+        let (top, left) = range.start().unwrap_or((0, 0));
+        let mut document = Document::default();
+        for (y, x, data) in range.used_cells(){
+            let cell = Cell{val: value(data), ..Cell::default()};
+            document.insert_cell(&Position{x: x + left as usize, y: y + top as usize}, &cell);
+        }
+        document.mark_saved();
+        sheets.push((name, document));
+    }
+    Ok(sheets)
+}
+
+fn value(data: &Data) -> DataType{
+    match data{
+        Data::Int(i) => DataType::Int(*i),
+        Data::Float(f) => DataType::Float(*f),
+        Data::String(s) => DataType::String(s.clone()),
+        Data::Bool(b) => DataType::Bool(*b),
+        Data::DateTime(d) if d.is_duration() => d.as_duration()
+            .map_or(DataType::Float(d.as_f64()), |delta| DataType::Duration(Duration{delta, style: DurationStyle::Clock})),
+        Data::DateTime(d) => match d.as_datetime(){
+            Some(d) if d.time() == NaiveTime::MIN => DataType::Date(d.date(), "%Y-%m-%d".to_string()),
+            Some(d) => DataType::DateTime(d, "%Y-%m-%d %H:%M:%S".to_string()),
+            None => DataType::Float(d.as_f64()),
+        },
+        Data::DateTimeIso(s) | Data::DurationIso(s) => temporal::parse(s, &[]).unwrap_or_else(|| DataType::String(s.clone())),
+        Data::Error(e) => DataType::String(e.to_string()),
+        Data::Empty => DataType::Empty,
+    }
+}
+
+/// Replaces `filename`, which must be an XLSX file, by the named sheets, keeping a copy of the previous version with `backup`
+///
+/// # Errors
+/// Will return an error when the format can't be written or writing the file fail, the previous version being kept
+pub fn write<'a, I>(filename: &str, backup: bool, sheets: I) -> Result<(), Error> where I: Iterator<Item = (&'a str, &'a Document)>{
+    if !is_writable(filename){
+        return Err(Error::new(ErrorKind::Unsupported, "Only XLSX spreadsheets can be written, save as .xlsx or .csv"));
+    }
+    let failed = |e: rust_xlsxwriter::XlsxError| Error::other(e.to_string());
+
+    let mut workbook = Workbook::new();
+    for (name, document) in sheets{
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(name).map_err(failed)?;
+        for (y, row) in document.rows.iter().enumerate(){
+            let y = u32::try_from(y).map_err(|_| Error::other("Too many rows"))?;
+            for (x, cell) in row.cells.iter().enumerate(){
+                let number_format = cell.style.format.or_else(|| document.col_format(x));
+                let number = cell_format(&cell.style, number_format.map(excel_format).as_deref());
+                let x = u16::try_from(x).map_err(|_| Error::other("Too many columns"))?;
+                #[allow(clippy::cast_precision_loss)]
+                let written = match &cell.val{
+                    DataType::Int(i) => worksheet.write_number_with_format(y, x, *i as f64, &number),
+                    DataType::Float(f) => worksheet.write_number_with_format(y, x, *f, &number),
+                    // Numbers read as text are written as numbers, unless that would change them (`007`)
+                    DataType::String(s) => match s.parse::<f64>(){
+                        Ok(n) if n.to_string() == *s => worksheet.write_number_with_format(y, x, n, &number),
+                        _ => worksheet.write_string_with_format(y, x, s, &number),
+                    },
+                    DataType::Bool(b) => worksheet.write_boolean_with_format(y, x, *b, &number),
+                    DataType::Date(d, _) => worksheet.write_datetime_with_format(y, x, d, &cell_format(&cell.style, Some("yyyy-mm-dd"))),
+                    DataType::DateTime(d, _) => worksheet.write_datetime_with_format(y, x, d, &cell_format(&cell.style, Some("yyyy-mm-dd hh:mm:ss"))),
+                    DataType::Duration(d) => {
+                        let days = d.delta.num_seconds() as f64 / 86400.0;
+                        worksheet.write_number_with_format(y, x, days, &cell_format(&cell.style, Some("[h]:mm:ss")))
+                    },
+                    // Only a styled empty cell is written
+                    DataType::Empty if cell.style.is_default() => continue,
+                    DataType::Empty => worksheet.write_blank(y, x, &number),
+                };
+                written.map_err(failed)?;
+            }
+        }
+    }
+    let content = workbook.save_to_buffer().map_err(failed)?;
+    atomic::write(filename, backup, |file| file.write_all(&content))
+}
+
+/// XLSX format of a cell with `style`, its numbers shown with `number_format`
+fn cell_format(style: &Style, number_format: Option<&str>) -> Format{
+    let color = |color| {
+        let (r, g, b) = style::rgb(color);
+        Color::RGB(u32::from(r) << 16 | u32::from(g) << 8 | u32::from(b))
+    };
+    let mut format = Format::new();
+    if style.bold{
+        format = format.set_bold();
+    }
+    if style.italic{
+        format = format.set_italic();
+    }
+    if style.underline{
+        format = format.set_underline(FormatUnderline::Single);
+    }
+    if let Some(fg) = style.fg{
+        format = format.set_font_color(color(fg));
+    }
+    if let Some(bg) = style.bg{
+        format = format.set_background_color(color(bg));
+    }
+    match style.align{
+        Align::Left => (),
+        Align::Center => format = format.set_align(FormatAlign::Center),
+        Align::Right => format = format.set_align(FormatAlign::Right),
+    }
+    if let Some(number_format) = number_format{
+        format = format.set_num_format(number_format);
+    }
+    format
+}
+
+/// XLSX number format code, always written with a decimal point and commas between thousands,
+/// the spreadsheet showing them the way of its locale
+fn excel_format(format: NumberFormat) -> String{
+    NumberFormat{decimal_comma: false, ..format}.spec()
+}
@@ -0,0 +1,87 @@
+//! What a file looked like when it was opened or saved, to tell when another program changed it since.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+
+#[derive(PartialEq, Clone, Copy)]
+pub struct Stamp{
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl Stamp{
+    /// Stamp of the file as it is now, `None` when it can't be read
+    #[must_use]
+    pub fn of(filename: &str) -> Option<Self>{
+        let metadata = fs::metadata(filename).ok()?;
+        let content = fs::read(filename).ok()?;
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        Some(Self{modified: metadata.modified().ok(), len: metadata.len(), hash: hasher.finish()})
+    }
+
+    /// Whether the file still holds what it held when stamped, its content being
+    /// read again only when its modification time or size changed. A file touched
+    /// without being changed gets its new modification time, to be read once only
+    pub fn matches(&mut self, filename: &str) -> bool{
+        match fs::metadata(filename){
+            Ok(metadata) if metadata.len() == self.len && metadata.modified().ok() == self.modified => true,
+            Ok(metadata) if metadata.len() != self.len => false,
+            Ok(_) => match Self::of(filename){
+                Some(now) if now.hash == self.hash => {
+                    self.modified = now.modified;
+                    true
+                },
+                _ => false,
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::time::Duration;
+
+    fn file(name: &str, content: &str) -> String{
+        let path = env::temp_dir().join(format!("rexcel-stamp-{}-{name}", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn touch(path: &str, seconds: u64){
+        let time = SystemTime::now() + Duration::from_secs(seconds);
+        File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn stamps_tell_when_the_content_changed(){
+        let path = file("changed", "a;1\n");
+        let mut stamp = Stamp::of(&path).unwrap();
+        assert!(stamp.matches(&path));
+
+        fs::write(&path, "a;2\n").unwrap();
+        touch(&path, 10);
+        assert!(!stamp.matches(&path));
+        fs::write(&path, "a;10\n").unwrap();
+        assert!(!stamp.matches(&path));
+        fs::remove_file(&path).unwrap();
+        assert!(!stamp.matches(&path));
+    }
+
+    #[test]
+    fn touched_files_keep_matching_with_their_new_time(){
+        let path = file("touched", "a;1\n");
+        let mut stamp = Stamp::of(&path).unwrap();
+        touch(&path, 10);
+        assert!(stamp.matches(&path));
+        assert_eq!(stamp.modified, fs::metadata(&path).unwrap().modified().ok());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,414 @@
+use std::env;
+use std::sync::OnceLock;
+
+use crossterm::style::Color;
+
+#[derive(PartialEq, Clone, Copy, Default)]
+pub enum Align{
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Currency symbols recognized at the start or the end of a number format
+const CURRENCIES: [char; 8] = ['$', '€', '£', '¥', '₹', '₽', '₩', '₺'];
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Notation{
+    Plain,
+    /// Value times 100, followed by `%`
+    Percent,
+    /// Mantissa and exponent, e.g. `1.23E+4`
+    Scientific,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub struct Currency{
+    pub symbol: char,
+    /// Written before the number (`$12`) rather than after it (`12 €`)
+    pub prefix: bool,
+}
+
+/// How numbers are displayed, the stored value being left untouched.
+///
+/// Written like a spreadsheet format: `0.00`, `#,##0`, `0.0%`, `0.00E+0`, `$#,##0.00`,
+/// and `#.##0,00€` for a decimal comma with dots between thousands. The formats written with a
+/// decimal point show the separators of the locale.
+#[derive(PartialEq, Clone, Copy)]
+pub struct NumberFormat{
+    pub decimals: usize,
+    /// Thousands separated
+    pub grouping: bool,
+    pub notation: Notation,
+    pub currency: Option<Currency>,
+    /// Decimal comma and dots between thousands whatever the locale, as written `#.##0,00`,
+    /// the formats written `#,##0.00` using the separators of the locale
+    pub decimal_comma: bool,
+}
+
+impl NumberFormat{
+    #[must_use]
+    pub fn parse(spec: &str) -> Option<Self>{
+        let mut body = spec;
+        let mut currency = None;
+        if let Some(symbol) = body.chars().next().filter(|c| CURRENCIES.contains(c)){
+            currency = Some(Currency{symbol, prefix: true});
+            body = &body[symbol.len_utf8()..];
+        }else if let Some(symbol) = body.chars().last().filter(|c| CURRENCIES.contains(c)){
+            currency = Some(Currency{symbol, prefix: false});
+            body = &body[..body.len() - symbol.len_utf8()];
+        }
+
+        let (body, notation) = if let Some(body) = body.strip_suffix('%'){
+            (body, Notation::Percent)
+        }else if let Some(body) = body.strip_suffix("E+0").or_else(|| body.strip_suffix("e+0")){
+            (body, Notation::Scientific)
+        }else{
+            (body, Notation::Plain)
+        };
+
+        // The last separator only followed by zeros starts the decimals, any other one groups thousands
+        let point = body.rfind(['.', ',']).filter(|i| body.len() > i + 1 && body[i + 1..].chars().all(|c| c == '0'));
+        let (integer, decimals) = point.map_or((body, ""), |i| (&body[..i], &body[i + 1..]));
+        if integer.is_empty() || !integer.ends_with('0') || !integer.chars().all(|c| "#0,.".contains(c)){
+            return None;
+        }
+
+        let decimal_comma = match point{
+            Some(i) => body[i..].starts_with(','),
+            None => integer.contains('.'),
+        };
+        let grouping = integer.contains([',', '.']);
+        if notation == Notation::Scientific && grouping{
+            return None;
+        }
+        Some(Self{decimals: decimals.len(), grouping, notation, currency, decimal_comma})
+    }
+
+    #[must_use]
+    pub fn spec(self) -> String{
+        let (point, separator) = if self.decimal_comma { (',', '.') } else { ('.', ',') };
+        let mut spec = if self.grouping { format!("#{separator}##0") } else { "0".to_string() };
+        if self.decimals > 0{
+            spec.push(point);
+            spec.push_str(&"0".repeat(self.decimals));
+        }
+        match self.notation{
+            Notation::Plain => (),
+            Notation::Percent => spec.push('%'),
+            Notation::Scientific => spec.push_str("E+0"),
+        }
+        match self.currency{
+            Some(Currency{symbol, prefix: true}) => format!("{symbol}{spec}"),
+            Some(Currency{symbol, prefix: false}) => format!("{spec}{symbol}"),
+            None => spec,
+        }
+    }
+
+    #[must_use]
+    pub fn format(self, n: f64) -> String{
+        self.format_with(n, if self.decimal_comma { (',', '.') } else { locale_separators() })
+    }
+
+    /// `n` formatted with the decimal point and the thousands separator `separators`
+    fn format_with(self, n: f64, separators: (char, char)) -> String{
+        let (point, separator) = separators;
+        let decimals = self.decimals;
+        let number = match self.notation{
+            Notation::Plain => format!("{:.decimals$}", n.abs()),
+            Notation::Percent => format!("{:.decimals$}%", n.abs() * 100.0),
+            Notation::Scientific => {
+                let scientific = format!("{:.decimals$e}", n.abs());
+                let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+                match exponent.strip_prefix('-'){
+                    Some(exponent) => format!("{mantissa}E-{exponent}"),
+                    None => format!("{mantissa}E+{exponent}"),
+                }
+            },
+        };
+
+        let (integer, rest) = number.find(|c: char| !c.is_ascii_digit()).map_or((number.as_str(), ""), |i| number.split_at(i));
+        let integer = if self.grouping { group(integer, separator) } else { integer.to_string() };
+        let rest = rest.replacen('.', &point.to_string(), 1);
+
+        // No minus sign when the value rounds to zero
+        let sign = if n < 0.0 && number.chars().any(|c| ('1'..='9').contains(&c)) { "-" } else { "" };
+        match self.currency{
+            Some(Currency{symbol, prefix: true}) => format!("{sign}{symbol}{integer}{rest}"),
+            Some(Currency{symbol, prefix: false}) => format!("{sign}{integer}{rest} {symbol}"),
+            None => format!("{sign}{integer}{rest}"),
+        }
+    }
+}
+
+/// Decimal point and thousands separator of the locale, read from `LC_ALL`, `LC_NUMERIC` or `LANG`
+fn locale_separators() -> (char, char){
+    static SEPARATORS: OnceLock<(char, char)> = OnceLock::new();
+    *SEPARATORS.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"].iter().filter_map(|name| env::var(name).ok()).find(|value| !value.is_empty());
+        separators(&locale.unwrap_or_default())
+    })
+}
+
+/// Decimal point and thousands separator written in `locale` (`de_DE.UTF-8`), a point and
+/// commas for the locales not known to use others
+fn separators(locale: &str) -> (char, char){
+    // Languages writing a decimal comma, with dots or spaces between thousands
+    const DOT_GROUPED: [&str; 22] = [
+        "az", "bs", "ca", "da", "de", "el", "es", "eu", "gl", "hr", "id",
+        "is", "it", "mk", "nl", "pt", "ro", "sl", "sr", "tr", "vi", "fo",
+    ];
+    const SPACE_GROUPED: [&str; 25] = [
+        "af", "be", "bg", "cs", "et", "fi", "fr", "hu", "hy", "ka", "kk", "ky", "lt",
+        "lv", "mn", "nb", "nn", "no", "pl", "ru", "sk", "sq", "sv", "uk", "uz",
+    ];
+    // Spanish speaking territories writing a decimal point
+    const POINT_TERRITORIES: [&str; 9] = ["MX", "US", "PR", "GT", "HN", "NI", "SV", "DO", "PA"];
+
+    let name = locale.split(['.', '@']).next().unwrap_or_default();
+    let (language, territory) = name.split_once('_').unwrap_or((name, ""));
+    if territory == "CH" || territory == "LI"{
+        ('.', '\'')
+    }else if language == "es" && POINT_TERRITORIES.contains(&territory){
+        ('.', ',')
+    }else if DOT_GROUPED.contains(&language){
+        (',', '.')
+    }else if SPACE_GROUPED.contains(&language){
+        (',', '\u{a0}')
+    }else{
+        ('.', ',')
+    }
+}
+
+/// Puts `separator` between each group of three digits
+fn group(digits: &str, separator: char) -> String{
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate(){
+        if i > 0 && (digits.len() - i).is_multiple_of(3){
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[derive(PartialEq, Clone, Default)]
+pub struct Style{
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub align: Align,
+    pub format: Option<NumberFormat>,
+}
+
+impl Style{
+    #[must_use]
+    pub fn is_default(&self) -> bool{
+        *self == Style::default()
+    }
+
+    /// Updates the style from a space separated spec such as `bold fg=red align=right fmt=0.00`.
+    /// `nobold`, `fg=none`, `fmt=none`... remove a setting and `reset` removes them all.
+    ///
+    /// # Errors
+    /// Will return the first word of the spec that can't be understood
+    pub fn apply(&mut self, spec: &str) -> Result<(), String>{
+        for word in spec.split_whitespace(){
+            let lower = word.to_lowercase();
+            match lower.split_once('='){
+                None => match lower.as_str(){
+                    "bold" => self.bold = true,
+                    "nobold" => self.bold = false,
+                    "italic" => self.italic = true,
+                    "noitalic" => self.italic = false,
+                    "underline" => self.underline = true,
+                    "nounderline" => self.underline = false,
+                    "reset" => *self = Style::default(),
+                    _ => return Err(word.to_string()),
+                },
+                Some(("fg", "none")) => self.fg = None,
+                Some(("bg", "none")) => self.bg = None,
+                Some(("fg", color)) => self.fg = Some(parse_color(color).ok_or_else(|| word.to_string())?),
+                Some(("bg", color)) => self.bg = Some(parse_color(color).ok_or_else(|| word.to_string())?),
+                Some(("align", "left")) => self.align = Align::Left,
+                Some(("align", "center")) => self.align = Align::Center,
+                Some(("align", "right")) => self.align = Align::Right,
+                Some(("fmt", "none")) => self.format = None,
+                Some(("fmt", format)) => self.format = Some(NumberFormat::parse(format).ok_or_else(|| word.to_string())?),
+                _ => return Err(word.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the settings of `other` on top of this style
+    pub fn overlay(&mut self, other: &Style){
+        self.bold |= other.bold;
+        self.italic |= other.italic;
+        self.underline |= other.underline;
+        self.fg = other.fg.or(self.fg);
+        self.bg = other.bg.or(self.bg);
+        if other.align != Align::Left{
+            self.align = other.align;
+        }
+        self.format = other.format.or(self.format);
+    }
+
+    /// Spec that `apply` turns back into this style
+    #[must_use]
+    pub fn spec(&self) -> String{
+        let mut words = Vec::new();
+        if self.bold{
+            words.push("bold".to_string());
+        }
+        if self.italic{
+            words.push("italic".to_string());
+        }
+        if self.underline{
+            words.push("underline".to_string());
+        }
+        if let Some(fg) = self.fg{
+            words.push(format!("fg={}", color_name(fg)));
+        }
+        if let Some(bg) = self.bg{
+            words.push(format!("bg={}", color_name(bg)));
+        }
+        match self.align{
+            Align::Left => (),
+            Align::Center => words.push("align=center".to_string()),
+            Align::Right => words.push("align=right".to_string()),
+        }
+        if let Some(format) = self.format{
+            words.push(format!("fmt={}", format.spec()));
+        }
+        words.join(" ")
+    }
+}
+
+const COLORS: [(&str, Color); 16] = [
+    ("black", Color::Black),
+    ("darkgrey", Color::DarkGrey),
+    ("red", Color::Red),
+    ("darkred", Color::DarkRed),
+    ("green", Color::Green),
+    ("darkgreen", Color::DarkGreen),
+    ("yellow", Color::Yellow),
+    ("darkyellow", Color::DarkYellow),
+    ("blue", Color::Blue),
+    ("darkblue", Color::DarkBlue),
+    ("magenta", Color::Magenta),
+    ("darkmagenta", Color::DarkMagenta),
+    ("cyan", Color::Cyan),
+    ("darkcyan", Color::DarkCyan),
+    ("white", Color::White),
+    ("grey", Color::Grey),
+];
+
+/// Reads a color name such as `red`, or a `#rrggbb` hex code
+#[must_use]
+pub fn parse_color(name: &str) -> Option<Color>{
+    if let Some(hex) = name.strip_prefix('#'){
+        if hex.len() != 6{
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb{r: channel(0)?, g: channel(2)?, b: channel(4)?});
+    }
+
+    COLORS.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+}
+
+#[must_use]
+pub fn color_name(color: Color) -> String{
+    if let Color::Rgb{r, g, b} = color{
+        return format!("#{r:02x}{g:02x}{b:02x}");
+    }
+    COLORS.iter().find(|(_, c)| *c == color).map_or_else(|| "none".to_string(), |(n, _)| (*n).to_string())
+}
+
+/// Usual terminal values of the named colors
+#[must_use]
+pub fn rgb(color: Color) -> (u8, u8, u8){
+    match color{
+        Color::Rgb{r, g, b} => (r, g, b),
+        Color::Black => (0, 0, 0),
+        Color::DarkGrey => (128, 128, 128),
+        Color::Red => (255, 0, 0),
+        Color::DarkRed => (128, 0, 0),
+        Color::Green => (0, 255, 0),
+        Color::DarkGreen => (0, 128, 0),
+        Color::Yellow => (255, 255, 0),
+        Color::DarkYellow => (128, 128, 0),
+        Color::Blue => (0, 0, 255),
+        Color::DarkBlue => (0, 0, 128),
+        Color::Magenta => (255, 0, 255),
+        Color::DarkMagenta => (128, 0, 128),
+        Color::Cyan => (0, 255, 255),
+        Color::DarkCyan => (0, 128, 128),
+        Color::Grey => (192, 192, 192),
+        _ => (255, 255, 255),
+    }
+}
+
+/// Color `ratio` of the way from `from` to `to`, `ratio` going from 0 to 1
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn blend(from: Color, to: Color, ratio: f64) -> Color{
+    let ratio = ratio.clamp(0.0, 1.0);
+    let ((r1, g1, b1), (r2, g2, b2)) = (rgb(from), rgb(to));
+    let mix = |a: u8, b: u8| (f64::from(a) + (f64::from(b) - f64::from(a)) * ratio).round() as u8;
+    Color::Rgb{r: mix(r1, r2), g: mix(g1, g2), b: mix(b1, b2)}
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn formats_are_parsed_and_written_back(){
+        for spec in ["0", "0.00", "#,##0", "0.0%", "0.00E+0", "$#,##0.00", "#.##0,00€"]{
+            assert_eq!(NumberFormat::parse(spec).map(NumberFormat::spec).as_deref(), Some(spec));
+        }
+        let format = NumberFormat::parse("#.##0,00€").unwrap();
+        assert!(format.decimal_comma && format.grouping);
+        assert_eq!(format.decimals, 2);
+        assert!(NumberFormat::parse("#,##0E+0").is_none());
+        assert!(NumberFormat::parse("abc").is_none());
+        assert!(NumberFormat::parse("").is_none());
+    }
+
+    #[test]
+    fn numbers_are_formatted(){
+        let format = |spec: &str, n: f64| NumberFormat::parse(spec).unwrap().format_with(n, ('.', ','));
+        assert_eq!(format("0.00", 1.23456), "1.23");
+        assert_eq!(format("#,##0", 1_234_567.8), "1,234,568");
+        assert_eq!(format("0.0%", 0.125), "12.5%");
+        assert_eq!(format("0.00E+0", 12345.0), "1.23E+4");
+        assert_eq!(format("0.00E+0", 0.00123), "1.23E-3");
+        assert_eq!(format("$#,##0.00", -1234.5), "-$1,234.50");
+        assert_eq!(format("0.00", -0.001), "0.00");
+    }
+
+    #[test]
+    fn decimal_comma_is_kept_whatever_the_locale(){
+        let format = NumberFormat::parse("#.##0,00€").unwrap();
+        assert_eq!(format.format(1234.5), "1.234,50 €");
+        assert_eq!(NumberFormat::parse("#,##0.00").unwrap().format_with(1234.5, separators("fr_FR.UTF-8")), "1\u{a0}234,50");
+    }
+
+    #[test]
+    fn separators_follow_the_locale(){
+        assert_eq!(separators(""), ('.', ','));
+        assert_eq!(separators("C"), ('.', ','));
+        assert_eq!(separators("en_US.UTF-8"), ('.', ','));
+        assert_eq!(separators("de_DE.UTF-8"), (',', '.'));
+        assert_eq!(separators("de_CH.UTF-8"), ('.', '\''));
+        assert_eq!(separators("fr_FR@euro"), (',', '\u{a0}'));
+        assert_eq!(separators("es_ES"), (',', '.'));
+        assert_eq!(separators("es_MX.UTF-8"), ('.', ','));
+        assert_eq!(separators("pt"), (',', '.'));
+    }
+}
@@ -0,0 +1,152 @@
+//! Autosave: while a file is edited, the values of its sheets are written now and then
+//! to `.<file>.swp` next to it, and removed when the editor quits normally. A swap file
+//! found at startup is left by a session that didn't, and can be recovered.
+//!
+//! Each sheet is a `sheet <rows> <name>` line followed by its rows, their cells separated by `;`
+//! with `\\`, `\;`, `\n` and `\r` standing for a backslash, a semicolon and line breaks.
+
+use std::fmt::Write;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::mem;
+use std::path::Path;
+
+use crate::atomic;
+use crate::Cell;
+use crate::Document;
+use crate::Row;
+
+#[must_use]
+pub fn path(filename: &str) -> String{
+    let path = Path::new(filename);
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(".{name}.swp")).to_string_lossy().into_owned()
+}
+
+#[must_use]
+pub fn exists(filename: &str) -> bool{
+    Path::new(&path(filename)).exists()
+}
+
+/// Writes the swap file of `filename` holding the named sheets
+///
+/// # Errors
+/// Will return an error when the swap file can't be written
+pub fn write<'a, I>(filename: &str, sheets: I) -> Result<(), Error> where I: Iterator<Item = (&'a str, &'a Document)>{
+    let mut content = String::new();
+    for (name, document) in sheets{
+        let _ = writeln!(content, "sheet {} {name}", document.rows.len());
+        for row in &document.rows{
+            content.push_str(&row.cells.iter().map(|cell| escape(&cell.val.to_string())).collect::<Vec<String>>().join(";"));
+            content.push('\n');
+        }
+    }
+    atomic::write(&path(filename), false, |file| file.write_all(content.as_bytes()))
+}
+
+/// `val` with the characters that would end the cell or the line escaped
+fn escape(val: &str) -> String{
+    let mut escaped = String::with_capacity(val.len());
+    for c in val.chars(){
+        match c{
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Values of the cells of a line written with `escape`
+fn unescape(line: &str) -> Vec<String>{
+    let (mut values, mut value) = (Vec::new(), String::new());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next(){
+        match c{
+            ';' => values.push(mem::take(&mut value)),
+            '\\' => match chars.next(){
+                Some('n') => value.push('\n'),
+                Some('r') => value.push('\r'),
+                Some(c) => value.push(c),
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+    values.push(value);
+    values
+}
+
+/// Reads the sheets kept in the swap file of `filename`, with their names
+///
+/// # Errors
+/// Will return an error when the swap file can't be read or was cut short
+pub fn read(filename: &str) -> Result<Vec<(String, Vec<Row>)>, Error>{
+    let content = fs::read_to_string(path(filename))?;
+    let invalid = || Error::new(ErrorKind::InvalidData, "Damaged swap file");
+
+    let mut sheets = Vec::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next(){
+        let (count, name) = line.strip_prefix("sheet ").and_then(|rest| rest.split_once(' ')).ok_or_else(invalid)?;
+        let count = count.parse::<usize>().map_err(|_| invalid())?;
+        let mut rows = Vec::new();
+        for _ in 0..count{
+            let cells: Vec<Cell> = unescape(lines.next().ok_or_else(invalid)?).into_iter().map(Cell::from).collect();
+            rows.push(Row{len: cells.len(), cells});
+        }
+        sheets.push((name.to_string(), rows));
+    }
+    Ok(sheets)
+}
+
+/// # Errors
+/// Will return an error when the swap file exists but can't be removed
+pub fn remove(filename: &str) -> Result<(), Error>{
+    match fs::remove_file(path(filename)){
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn cells_are_escaped_and_read_back(){
+        let values = ["a;b", "back\\slash", "two\nlines\r\n", "", "\\n", "plain"];
+        let line = values.iter().map(|val| escape(val)).collect::<Vec<String>>().join(";");
+        assert!(!line.contains(['\n', '\r']));
+        assert_eq!(unescape(&line), values);
+        assert_eq!(unescape(""), [""]);
+        assert_eq!(unescape("a\\"), ["a\\"]);
+    }
+
+    #[test]
+    fn swap_files_sit_hidden_next_to_the_file(){
+        assert_eq!(path("data.csv"), ".data.csv.swp");
+        assert_eq!(path("dir/data.csv"), "dir/.data.csv.swp");
+    }
+
+    #[test]
+    fn sheets_are_written_and_read_back(){
+        let filename = env::temp_dir().join(format!("rexcel-swap-{}.csv", std::process::id())).to_string_lossy().into_owned();
+        let mut first = Document::default();
+        first.replace_rows(vec![Row::parse("a;b", ','), Row::parse("multi\nline", ',')]);
+        let second = Document::default();
+        write(&filename, vec![("Sheet1", &first), ("Q1 sales", &second)].into_iter()).unwrap();
+        assert!(exists(&filename));
+
+        let sheets = read(&filename).unwrap();
+        let text: Vec<(String, Vec<String>)> = sheets.iter().map(|(name, rows)| (name.clone(), rows.iter().map(|row| row.stringify("|")).collect())).collect();
+        assert_eq!(text, [("Sheet1".to_string(), vec!["a;b".to_string(), "multi\nline".to_string()]), ("Q1 sales".to_string(), vec![])]);
+
+        remove(&filename).unwrap();
+        assert!(!exists(&filename));
+        assert!(remove(&filename).is_ok());
+    }
+}
@@ -0,0 +1,240 @@
+//! Dates, date-times and durations held by cells. They are read with the ISO 8601
+//! patterns or the ones of the document, and written back with the pattern they were read with.
+
+use std::fmt::{self, Display, Write};
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+
+use crate::cell::DataType;
+
+/// Patterns always tried, before the ones of the document
+const ISO_PATTERNS: [&str; 5] = [
+    "%Y-%m-%d",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.fZ",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+];
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum DurationStyle{
+    /// `1:30:00`, hours not being limited to 24
+    Clock,
+    /// `P2DT1H30M`
+    Iso,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Duration{
+    pub delta: TimeDelta,
+    pub style: DurationStyle,
+}
+
+impl Display for Duration{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        let sign = if self.delta < TimeDelta::zero() { "-" } else { "" };
+        let seconds = self.delta.num_seconds().unsigned_abs();
+        let (days, hours, minutes, seconds) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+        match self.style{
+            DurationStyle::Clock => write!(f, "{sign}{}:{minutes:02}:{seconds:02}", days * 24 + hours),
+            DurationStyle::Iso => {
+                write!(f, "{sign}P")?;
+                if days > 0{
+                    write!(f, "{days}D")?;
+                }
+                if hours > 0 || minutes > 0 || seconds > 0 || days == 0{
+                    write!(f, "T")?;
+                }
+                for (n, unit) in [(hours, 'H'), (minutes, 'M'), (seconds, 'S')]{
+                    if n > 0{
+                        write!(f, "{n}{unit}")?;
+                    }
+                }
+                if days == 0 && hours == 0 && minutes == 0 && seconds == 0{
+                    write!(f, "0S")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Reads `s` as a date, a date-time or a duration, trying the ISO 8601 patterns then `patterns`.
+/// The value must be written back exactly as `s`, so `2024-1-5` stays text with `%Y-%m-%d`.
+#[must_use]
+pub fn parse(s: &str, patterns: &[String]) -> Option<DataType>{
+    if s.trim().is_empty(){
+        return None;
+    }
+
+    ISO_PATTERNS.iter().copied().chain(patterns.iter().map(String::as_str))
+        .find_map(|pattern| {
+            NaiveDateTime::parse_from_str(s, pattern).map(|d| DataType::DateTime(d, pattern.to_string()))
+                .or_else(|_| NaiveDate::parse_from_str(s, pattern).map(|d| DataType::Date(d, pattern.to_string())))
+                .ok()
+                .filter(|val| writes_back(val, s))
+        })
+        .or_else(|| parse_duration(s).map(DataType::Duration).filter(|val| writes_back(val, s)))
+}
+
+/// Whether `val` is written as `s`, a pattern with fields the value lacks (a date with `%H`) failing to write it
+fn writes_back(val: &DataType, s: &str) -> bool{
+    let mut text = String::new();
+    write!(text, "{val}").is_ok() && text == s
+}
+
+/// Reads `[-]h:mm:ss` or an ISO 8601 duration such as `P1DT2H30M`
+fn parse_duration(s: &str) -> Option<Duration>{
+    let (negative, s) = s.strip_prefix('-').map_or((false, s), |s| (true, s));
+    let seconds = if let Some(iso) = s.strip_prefix('P'){
+        parse_iso_duration(iso)?
+    }else{
+        let parts: Vec<&str> = s.split(':').collect();
+        let [hours, minutes, seconds] = parts.as_slice() else{
+            return None;
+        };
+        if minutes.len() != 2 || seconds.len() != 2 || !hours.chars().all(|c| c.is_ascii_digit()){
+            return None;
+        }
+        let (minutes, seconds) = (minutes.parse::<i64>().ok()?, seconds.parse::<i64>().ok()?);
+        if minutes >= 60 || seconds >= 60{
+            return None;
+        }
+        hours.parse::<i64>().ok()?.checked_mul(3600)?.checked_add(minutes * 60 + seconds)?
+    };
+
+    let style = if s.starts_with('P') { DurationStyle::Iso } else { DurationStyle::Clock };
+    let delta = TimeDelta::try_seconds(if negative { -seconds } else { seconds })?;
+    Some(Duration{delta, style})
+}
+
+/// Seconds of the part of an ISO 8601 duration following the `P`, weeks, days, hours, minutes and seconds
+fn parse_iso_duration(s: &str) -> Option<i64>{
+    let (date, time) = s.split_once('T').unwrap_or((s, ""));
+    if date.is_empty() && time.is_empty(){
+        return None;
+    }
+
+    let date_units: &[(char, i64)] = &[('W', 604_800), ('D', 86400)];
+    let time_units: &[(char, i64)] = &[('H', 3600), ('M', 60), ('S', 1)];
+    let mut total: i64 = 0;
+    for (part, units) in [(date, date_units), (time, time_units)]{
+        let mut rest = part;
+        while !rest.is_empty(){
+            let end = rest.find(|c: char| !c.is_ascii_digit())?;
+            let unit = rest[end..].chars().next()?;
+            let (_, seconds) = units.iter().find(|(u, _)| *u == unit)?;
+            total = total.checked_add(rest[..end].parse::<i64>().ok()?.checked_mul(*seconds)?)?;
+            rest = &rest[end + unit.len_utf8()..];
+        }
+    }
+    Some(total)
+}
+
+/// `a + b`: a duration or a number of days added to a date, or two durations added together
+#[must_use]
+pub fn add(a: &DataType, b: &DataType) -> Option<DataType>{
+    match (a, b){
+        (DataType::Date(date, pattern), DataType::Duration(d)) | (DataType::Duration(d), DataType::Date(date, pattern)) => {
+            if d.delta.num_seconds() % 86400 == 0{
+                Some(DataType::Date(date.checked_add_signed(d.delta)?, pattern.clone()))
+            }else{
+                let datetime = date.and_hms_opt(0, 0, 0)?.checked_add_signed(d.delta)?;
+                Some(DataType::DateTime(datetime, format!("{pattern} %H:%M:%S")))
+            }
+        },
+        (DataType::DateTime(datetime, pattern), DataType::Duration(d)) | (DataType::Duration(d), DataType::DateTime(datetime, pattern)) => {
+            Some(DataType::DateTime(datetime.checked_add_signed(d.delta)?, pattern.clone()))
+        },
+        (DataType::Duration(x), DataType::Duration(y)) => Some(DataType::Duration(Duration{delta: x.delta.checked_add(&y.delta)?, style: x.style})),
+        (DataType::Date(date, pattern), n) | (n, DataType::Date(date, pattern)) => {
+            Some(DataType::Date(date.checked_add_signed(days(n)?)?, pattern.clone()))
+        },
+        _ => None,
+    }
+}
+
+/// `a - b`: the duration between two dates or date-times, or `add` with `b` negated
+#[must_use]
+pub fn sub(a: &DataType, b: &DataType) -> Option<DataType>{
+    let between = |delta| Some(DataType::Duration(Duration{delta, style: DurationStyle::Iso}));
+    match (a, b){
+        (DataType::Date(x, _), DataType::Date(y, _)) => between(x.signed_duration_since(*y)),
+        (DataType::DateTime(x, _), DataType::DateTime(y, _)) => between(x.signed_duration_since(*y)),
+        (DataType::DateTime(x, _), DataType::Date(y, _)) => between(x.signed_duration_since(y.and_hms_opt(0, 0, 0)?)),
+        (DataType::Date(x, _), DataType::DateTime(y, _)) => between(x.and_hms_opt(0, 0, 0)?.signed_duration_since(*y)),
+        (_, DataType::Duration(d)) => add(a, &DataType::Duration(Duration{delta: -d.delta, style: d.style})),
+        (DataType::Date(date, pattern), n) => Some(DataType::Date(date.checked_sub_signed(days(n)?)?, pattern.clone())),
+        _ => None,
+    }
+}
+
+/// Whole number of days held by a number
+#[allow(clippy::cast_possible_truncation)]
+fn days(val: &DataType) -> Option<TimeDelta>{
+    let n = val.as_number().filter(|n| n.fract() == 0.0)?;
+    TimeDelta::try_days(n as i64)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn parsed(s: &str) -> Option<DataType>{
+        parse(s, &["%d/%m/%Y".to_string(), "%d/%m/%Y %H:%M".to_string()])
+    }
+
+    fn value(s: &str) -> DataType{
+        parsed(s).unwrap_or_else(|| s.parse().map_or_else(|_| DataType::String(s.to_string()), DataType::Int))
+    }
+
+    fn text(val: Option<DataType>) -> Option<String>{
+        val.map(|val| val.to_string())
+    }
+
+    #[test]
+    fn values_are_read_and_written_back_as_typed(){
+        assert!(matches!(parsed("2024-01-05"), Some(DataType::Date(..))));
+        assert!(matches!(parsed("2024-01-05T10:30:00"), Some(DataType::DateTime(..))));
+        assert!(matches!(parsed("05/01/2024"), Some(DataType::Date(..))));
+        assert!(matches!(parsed("05/01/2024 10:30"), Some(DataType::DateTime(..))));
+        for s in ["2024-01-05", "2024-01-05T10:30:00", "2024-01-05 10:30:00.500", "05/01/2024", "1:30:00", "-0:00:05", "P1DT2H", "P14D", "PT0S"]{
+            assert_eq!(text(parsed(s)).as_deref(), Some(s));
+        }
+        for s in ["2024-1-5", "5/1/2024", "2024-02-30", "1:60:00", "1:5:00", "P", "PT", "P1X", "P1W", "12", "", "late"]{
+            assert!(parsed(s).is_none(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn durations_are_written_in_their_style(){
+        let duration = |seconds, style| Duration{delta: TimeDelta::seconds(seconds), style}.to_string();
+        assert_eq!(duration(93_784, DurationStyle::Clock), "26:03:04");
+        assert_eq!(duration(93_784, DurationStyle::Iso), "P1DT2H3M4S");
+        assert_eq!(duration(-90, DurationStyle::Clock), "-0:01:30");
+        assert_eq!(duration(172_800, DurationStyle::Iso), "P2D");
+        assert_eq!(duration(0, DurationStyle::Iso), "PT0S");
+        assert_eq!(parse_duration("P1W").map(|d| d.delta.num_seconds()), Some(604_800));
+    }
+
+    #[test]
+    fn dates_and_durations_add_up(){
+        assert_eq!(text(add(&value("2024-01-30"), &value("P2D"))).as_deref(), Some("2024-02-01"));
+        assert_eq!(text(add(&value("30"), &value("05/01/2024"))).as_deref(), Some("04/02/2024"));
+        assert_eq!(text(add(&value("2024-01-05"), &value("PT1H"))).as_deref(), Some("2024-01-05 01:00:00"));
+        assert_eq!(text(add(&value("2024-01-05T23:00:00"), &value("2:00:00"))).as_deref(), Some("2024-01-06T01:00:00"));
+        assert_eq!(text(add(&value("1:30:00"), &value("PT45M"))).as_deref(), Some("2:15:00"));
+        assert!(add(&value("2024-01-05"), &value("2024-01-05")).is_none());
+        assert!(add(&value("2024-01-05"), &DataType::Float(1.5)).is_none());
+        assert!(add(&value("1:00:00"), &value("3")).is_none());
+    }
+
+    #[test]
+    fn dates_subtract_to_durations(){
+        assert_eq!(text(sub(&value("2024-03-01"), &value("2024-02-01"))).as_deref(), Some("P29D"));
+        assert_eq!(text(sub(&value("2024-01-01"), &value("2024-01-01T12:00:00"))).as_deref(), Some("-PT12H"));
+        assert_eq!(text(sub(&value("2024-03-01"), &value("1"))).as_deref(), Some("2024-02-29"));
+        assert_eq!(text(sub(&value("2024-03-01"), &value("P7D"))).as_deref(), Some("2024-02-23"));
+        assert!(sub(&value("3"), &value("2024-03-01")).is_none());
+    }
+}
@@ -1,10 +1,17 @@
-use crossterm::event::{Event, read};
+use crossterm::event::{self, Event, read, EnableMouseCapture, DisableMouseCapture};
 use crossterm::terminal;
 use crossterm::{execute, ErrorKind};
 
 
 use crate::Position;
+use std::convert::TryFrom;
 use std::io::{stdout, Write};
+use std::panic;
+use std::time::Duration;
+
+/// Lines of the screen not used by the grid rows: status line, sheet tabs,
+/// edit line, column headers and the last line
+const RESERVED_LINES: usize = 5;
 
 #[derive(Clone, Copy)]
 pub struct Size{
@@ -17,38 +24,63 @@ pub struct Terminal{
 }
 
 impl Terminal{
+    /// # Errors
+    /// Will return an error when the terminal size can't be read
     pub fn new() -> Result<Self, ErrorKind>{
         let size = terminal::size()?;
         Ok(Self{
             size: Size{
                 width: size.0 as usize,
-                height: size.1.saturating_sub(4) as usize
+                height: (size.1 as usize).saturating_sub(RESERVED_LINES)
             }
         })
     }
 
+    /// Switches to raw mode and the alternate screen, which are left before any panic message is printed
+    ///
+    /// # Panics
+    /// Will panic if the terminal can't be switched to raw mode
     pub fn enter(){
+        let print_panic = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            Self::restore();
+            print_panic(info);
+        }));
+
         terminal::enable_raw_mode().unwrap();
-        execute!(stdout(), terminal::EnterAlternateScreen).unwrap();
+        execute!(stdout(), terminal::EnterAlternateScreen, EnableMouseCapture).unwrap();
     }
 
+    /// Leaves raw mode and the alternate screen, ignoring failures since it may run while panicking
+    pub fn restore(){
+        let _ = execute!(stdout(), DisableMouseCapture, terminal::LeaveAlternateScreen, crossterm::cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+
+    #[must_use]
     pub fn size(&self) -> Size{
         self.size
     }
 
+    /// # Errors
+    /// Will return an error when writing to stdout fail
     pub fn update_size(&mut self, width: usize, height: usize) -> Result<(), ErrorKind>{
-        let new_size = Size{width, height: height.saturating_sub(4)};
-        write!(stdout(), "{}|{}", width, height)?;
+        let new_size = Size{width, height: height.saturating_sub(RESERVED_LINES)};
+        write!(stdout(), "{width}|{height}")?;
         self.size = new_size;
         Ok(())
     }
 
+    /// # Panics
+    /// Will panic if the terminal can't leave raw mode
     pub fn leave(){
         terminal::disable_raw_mode().unwrap();
-        execute!(stdout(), terminal::LeaveAlternateScreen).unwrap();
+        execute!(stdout(), DisableMouseCapture, terminal::LeaveAlternateScreen).unwrap();
         println!("Goodbye!");
     }
 
+    /// # Errors
+    /// Will return an error when flushing stdout fail
     pub fn flush() -> Result<(), std::io::Error>{
         stdout().flush()
     }
@@ -77,11 +109,29 @@ impl Terminal{
         execute!(stdout(), crossterm::style::SetBackgroundColor(color)).unwrap();
     }
 
+    pub fn set_attribute(attribute: crossterm::style::Attribute){
+        execute!(stdout(), crossterm::style::SetAttribute(attribute)).unwrap();
+    }
+
+    pub fn reset_attributes(){
+        execute!(stdout(), crossterm::style::SetAttribute(crossterm::style::Attribute::Reset)).unwrap();
+    }
+
     pub fn reset_colors(){
         execute!(stdout(), crossterm::style::ResetColor).unwrap();
     }
 
 
+    /// Waits at most `timeout` for an event to be ready to read
+    ///
+    /// # Errors
+    /// Will return an error when the terminal can't be polled
+    pub fn poll(timeout: Duration) -> Result<bool, ErrorKind>{
+        event::poll(timeout)
+    }
+
+    /// # Errors
+    /// Never returns an error, read failures are retried
     pub fn read_event() -> Result<Event, ErrorKind>{
         loop{
             if let Ok(event) = read(){
@@ -91,6 +141,6 @@ impl Terminal{
     }
 
     pub fn goto(p: &Position){
-        execute!(stdout(), crossterm::cursor::MoveTo(p.x as u16, p.y as u16)).unwrap();
+        execute!(stdout(), crossterm::cursor::MoveTo(u16::try_from(p.x).unwrap_or(u16::MAX), u16::try_from(p.y).unwrap_or(u16::MAX))).unwrap();
     }
 }
\ No newline at end of file
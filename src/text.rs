@@ -0,0 +1,64 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Number of terminal cells taken by `s`
+#[must_use]
+pub fn width(s: &str) -> usize{
+    UnicodeWidthStr::width(s)
+}
+
+/// Cuts `s` to at most `max` terminal cells, ending with `..` when shortened.
+/// Grapheme clusters are never split.
+#[must_use]
+pub fn truncate(s: &str, max: usize) -> String{
+    if width(s) <= max{
+        return s.to_string();
+    }
+
+    let marker = if max > 2 { ".." } else { "" };
+    let room = max.saturating_sub(marker.len());
+    let mut result = String::new();
+    let mut used = 0;
+    for g in s.graphemes(true){
+        let w = width(g);
+        if used + w > room{
+            break;
+        }
+        used += w;
+        result.push_str(g);
+    }
+    result.push_str(marker);
+    result
+}
+
+/// Truncates then pads `s` with spaces to exactly `cells` terminal cells
+#[must_use]
+pub fn pad_right(s: &str, cells: usize) -> String{
+    let s = truncate(s, cells);
+    let margin = cells.saturating_sub(width(&s));
+    format!("{}{}", s, " ".repeat(margin))
+}
+
+/// Truncates then pads `s` with spaces on the left to exactly `cells` terminal cells
+#[must_use]
+pub fn pad_left(s: &str, cells: usize) -> String{
+    let s = truncate(s, cells);
+    let margin = cells.saturating_sub(width(&s));
+    format!("{}{}", " ".repeat(margin), s)
+}
+
+/// Truncates then centers `s` in exactly `cells` terminal cells
+#[must_use]
+pub fn center(s: &str, cells: usize) -> String{
+    let s = truncate(s, cells);
+    let margin = cells.saturating_sub(width(&s));
+    let left = margin / 2;
+    format!("{}{}{}", " ".repeat(left), s, " ".repeat(margin - left))
+}
+
+/// Removes the last grapheme cluster of `s`
+pub fn pop_grapheme(s: &mut String){
+    if let Some((at, _)) = s.grapheme_indices(true).next_back(){
+        s.truncate(at);
+    }
+}
@@ -0,0 +1,339 @@
+//! Named sheets of the open file.
+//!
+//! Spreadsheet files hold all their sheets. With a delimited file, the file holds the
+//! first sheet and each other one is kept next to it, `data.csv` sheet `Totals` going
+//! to `data.Totals.csv`. The sheet names are listed in the sidecar of the first one.
+//! The layout of each sheet of a spreadsheet is kept in the sidecar of its own name,
+//! `book.xlsx` sheet `Totals` using `book.Totals.xlsx.rexcel`.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::mem;
+use std::path::Path;
+
+use crate::sidecar;
+use crate::follow::Follower;
+use crate::spreadsheet;
+use crate::stamp::Stamp;
+use crate::Document;
+use crate::History;
+use crate::Position;
+
+/// A sheet and where the editor was on it
+pub struct Sheet{
+    pub name: String,
+    pub document: Document,
+    pub cursor: Position,
+    pub offset: Position,
+    pub frozen: Position,
+    pub history: History,
+}
+
+impl Sheet{
+    #[must_use]
+    pub fn new(name: &str, document: Document) -> Self{
+        Self{
+            name: name.to_string(),
+            document,
+            cursor: Position::default(),
+            offset: Position::default(),
+            frozen: Position::default(),
+            history: History::default(),
+        }
+    }
+}
+
+pub struct Workbook{
+    pub file_name: Option<String>,
+    pub sheets: Vec<Sheet>,
+    /// Index of the sheet being edited
+    pub active: usize,
+    /// Files of the removed or renamed sheets, deleted on save
+    stale: Vec<String>,
+    /// Whether the previous version of each file is kept in `<file>.bak` on save
+    pub backup: bool,
+    /// Whether the editor offers to reload the file when another program changes it
+    pub watch: bool,
+    /// Reader of the lines appended to the file, which is then read-only
+    pub follow: Option<Follower>,
+    /// Files as they were opened or last saved: the file and, for a delimited file, those of the other sheets
+    stamps: Vec<(String, Stamp)>,
+    /// Files as changed by another program when the user chose not to reload them
+    dismissed: Vec<(String, Stamp)>,
+    /// Whether sheets were added, renamed, moved or removed, or the file renamed, since the last save
+    changed: bool,
+    /// Spreadsheet last written by the editor, which loses nothing by being written again
+    rewritten: Option<String>,
+}
+
+impl Default for Workbook{
+    fn default() -> Self{
+        Self::new(None, Document::default())
+    }
+}
+
+impl Workbook{
+    /// Workbook holding only `document`
+    #[must_use]
+    pub fn new(file_name: Option<String>, document: Document) -> Self{
+        Self{
+            file_name,
+            sheets: vec![Sheet::new("Sheet1", document)],
+            active: 0,
+            stale: Vec::new(),
+            backup: false,
+            watch: false,
+            follow: None,
+            stamps: Vec::new(),
+            dismissed: Vec::new(),
+            changed: false,
+            rewritten: None,
+        }
+    }
+
+    /// # Errors
+    /// Will return an error when the file, or the one of a sheet listed in its sidecar, can't be read
+    pub fn open(filename: &str) -> Result<Self, std::io::Error>{
+        let stamp = Stamp::of(filename);
+        if spreadsheet::is_spreadsheet(filename){
+            let mut workbook = Self::new(Some(filename.to_string()), Document::default());
+            workbook.sheets = spreadsheet::read(filename)?.into_iter().map(|(name, mut document)| {
+                let path = sheet_path(filename, &name);
+                sidecar::load(&path, &mut document);
+                document.file_name = Some(path);
+                document.mark_saved();
+                Sheet::new(&name, document)
+            }).collect();
+            if workbook.sheets.is_empty(){
+                workbook.sheets.push(Sheet::new("Sheet1", Document::default()));
+            }
+            workbook.stamps.extend(stamp.map(|stamp| (filename.to_string(), stamp)));
+            return Ok(workbook);
+        }
+
+        let mut first = Document::open(filename)?;
+        let names = mem::take(&mut first.sheet_names);
+        let mut workbook = Self::new(Some(filename.to_string()), first);
+        workbook.stamps.extend(stamp.map(|stamp| (filename.to_string(), stamp)));
+        if let Some(name) = names.first(){
+            workbook.sheets[0].name.clone_from(name);
+        }
+        for name in names.iter().skip(1){
+            let path = sheet_path(filename, name);
+            let stamp = Stamp::of(&path);
+            let document = match Document::open(&path){
+                Err(e) if e.kind() == ErrorKind::NotFound => Document::default(),
+                result => result?,
+            };
+            workbook.stamps.extend(stamp.map(|stamp| (path, stamp)));
+            workbook.sheets.push(Sheet::new(name, document));
+        }
+        Ok(workbook)
+    }
+
+    /// Writes every sheet, the one being edited must have been put back in the workbook
+    ///
+    /// # Errors
+    /// Will return an error when writing a file to the disk fail
+    pub fn save(&mut self) -> Result<(), std::io::Error>{
+        let Some(filename) = self.file_name.clone() else{
+            return Ok(());
+        };
+        if spreadsheet::is_spreadsheet(&filename){
+            spreadsheet::write(&filename, self.backup, self.sheets.iter().map(|sheet| (sheet.name.as_str(), &sheet.document)))?;
+            for sheet in &mut self.sheets{
+                let path = sheet_path(&filename, &sheet.name);
+                sidecar::save(&path, &sheet.document)?;
+                if let Some(old) = sheet.document.file_name.replace(path.clone()){
+                    if old != path{
+                        self.stale.push(old);
+                    }
+                }
+                sheet.document.mark_saved();
+            }
+            // The sheets are in the spreadsheet, only their sidecars are files of their own
+            self.remove_stale(false)?;
+            self.rewritten = Some(filename);
+            self.changed = false;
+            self.stamp_file();
+            return Ok(());
+        }
+
+        let names: Vec<String> = self.sheets.iter().map(|sheet| sheet.name.clone()).collect();
+        for (i, sheet) in self.sheets.iter_mut().enumerate(){
+            let path = if i == 0 { filename.clone() } else { sheet_path(&filename, &sheet.name) };
+            if let Some(old) = sheet.document.file_name.replace(path.clone()){
+                if old != path{
+                    self.stale.push(old);
+                }
+            }
+            // A single sheet keeping its default name needs no listing
+            sheet.document.sheet_names = if i == 0 && (names.len() > 1 || names[0] != "Sheet1") { names.clone() } else { Vec::new() };
+            sheet.document.save(self.backup)?;
+        }
+
+        self.remove_stale(true)?;
+        self.changed = false;
+        self.stamp_file();
+        Ok(())
+    }
+
+    /// Removes the sidecars, and with `files` the files, of the sheets removed or renamed
+    fn remove_stale(&mut self, files: bool) -> Result<(), std::io::Error>{
+        // Compared ignoring case, in case the file system does too
+        let kept: Vec<&String> = self.sheets.iter().filter_map(|sheet| sheet.document.file_name.as_ref()).collect();
+        for path in self.stale.drain(..).filter(|path| !kept.iter().any(|k| k.eq_ignore_ascii_case(path))){
+            let sidecar = sidecar::path(&path);
+            for file in [Some(sidecar), Some(path).filter(|_| files)].iter().flatten(){
+                match fs::remove_file(file){
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                    _ => (),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether saving would replace a spreadsheet the editor didn't write, losing what it doesn't
+    /// read: formulas, number formats of the cells, merged cells, charts
+    #[must_use]
+    pub fn overwrites_spreadsheet(&self) -> bool{
+        self.file_name.as_deref().is_some_and(|filename| {
+            spreadsheet::is_spreadsheet(filename) && Path::new(filename).exists() && self.rewritten.as_deref() != Some(filename)
+        })
+    }
+
+    /// Stamps of the files the workbook is saved to, as they are now
+    fn stamp_files(&self) -> Vec<(String, Stamp)>{
+        let Some(filename) = &self.file_name else{
+            return Vec::new();
+        };
+        let mut files = vec![filename.clone()];
+        // The sheets of a spreadsheet are in its file
+        if !spreadsheet::is_spreadsheet(filename){
+            files.extend(self.sheets.iter().skip(1).filter_map(|sheet| sheet.document.file_name.clone()));
+        }
+        files.into_iter().filter_map(|file| Stamp::of(&file).map(|stamp| (file, stamp))).collect()
+    }
+
+    fn stamp_file(&mut self){
+        self.stamps = self.stamp_files();
+        self.dismissed.clear();
+    }
+
+    /// Whether another program changed one of the files since they were opened or saved
+    pub fn changed_on_disk(&mut self) -> bool{
+        !self.stamps.iter_mut().all(|(file, stamp)| stamp.matches(file))
+    }
+
+    /// Whether the change made by another program is the one the user chose not to reload
+    pub fn disk_change_dismissed(&mut self) -> bool{
+        !self.dismissed.is_empty() && self.dismissed.iter_mut().all(|(file, stamp)| stamp.matches(file))
+    }
+
+    /// Keeps the files as they are in the editor despite the change made by another program, until they change again
+    pub fn dismiss_disk_change(&mut self){
+        self.dismissed = self.stamp_files();
+    }
+
+    /// Whether something wasn't saved, the sheet being edited must have been put back in the workbook
+    #[must_use]
+    pub fn is_dirty(&self) -> bool{
+        self.changed || self.sheets.iter().any(|sheet| sheet.document.is_dirty())
+    }
+
+    /// Saves the workbook under another name from now on, leaving the files of the previous one alone
+    pub fn set_file_name(&mut self, filename: String){
+        self.file_name = Some(filename);
+        self.stale.clear();
+        self.changed = true;
+        self.stamps.clear();
+        self.dismissed.clear();
+        for sheet in &mut self.sheets{
+            sheet.document.file_name = None;
+        }
+    }
+
+    #[must_use]
+    pub fn index_of(&self, name: &str) -> Option<usize>{
+        self.sheets.iter().position(|sheet| sheet.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Name for a new sheet, `Sheet2`, `Sheet3`...
+    #[must_use]
+    pub fn next_name(&self) -> String{
+        // One of the first len + 1 names is free
+        (1..=self.sheets.len() + 1).map(|i| format!("Sheet{i}")).find(|name| self.index_of(name).is_none()).unwrap_or_default()
+    }
+
+    /// # Errors
+    /// Will return why `name` can't be given to a sheet
+    pub fn check_name(&self, name: &str) -> Result<(), String>{
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || "!/\\:*?[]'".contains(c)){
+            return Err(format!("Invalid sheet name: {name}"));
+        }
+        if self.index_of(name).is_some(){
+            return Err(format!("A sheet is already named {name}"));
+        }
+        Ok(())
+    }
+
+    /// Adds an empty sheet after the active one and returns its index
+    ///
+    /// # Errors
+    /// Will return why `name` can't be given to a sheet
+    pub fn add(&mut self, name: &str) -> Result<usize, String>{
+        self.check_name(name)?;
+        let at = self.active + 1;
+        self.sheets.insert(at, Sheet::new(name, Document::default()));
+        self.changed = true;
+        Ok(at)
+    }
+
+    /// # Errors
+    /// Will return why `name` can't be given to a sheet
+    pub fn rename(&mut self, index: usize, name: &str) -> Result<(), String>{
+        if !self.sheets[index].name.eq_ignore_ascii_case(name){
+            self.check_name(name)?;
+        }
+        self.sheets[index].name = name.to_string();
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Moves the active sheet one place to the left or to the right
+    pub fn move_active(&mut self, right: bool){
+        let to = if right { self.active + 1 } else { self.active.wrapping_sub(1) };
+        if to < self.sheets.len(){
+            self.sheets.swap(self.active, to);
+            self.active = to;
+            self.changed = true;
+        }
+    }
+
+    /// Removes a sheet, the last one excepted
+    pub fn remove(&mut self, index: usize) -> Option<Sheet>{
+        if self.sheets.len() < 2{
+            return None;
+        }
+        let sheet = self.sheets.remove(index);
+        self.changed = true;
+        self.stale.extend(sheet.document.file_name.clone());
+        if index < self.active || self.active == self.sheets.len(){
+            self.active -= 1;
+        }
+        Some(sheet)
+    }
+}
+
+/// File holding the sheet `name` of the delimited file `filename`
+#[must_use]
+pub fn sheet_path(filename: &str, name: &str) -> String{
+    let path = Path::new(filename);
+    let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let file = match path.extension(){
+        Some(ext) => format!("{stem}.{name}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{name}"),
+    };
+    path.with_file_name(file).to_string_lossy().into_owned()
+}